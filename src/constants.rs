@@ -1,3 +0,0 @@
-pub const HANDSHAKE_PACKET: i32 = 0x00;
-pub const PONG_PACKET: i32 = 0x01;
-pub const CHAT_MESSAGE_PACKET: i32 = 0x03;
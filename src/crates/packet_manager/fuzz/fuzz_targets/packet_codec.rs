@@ -0,0 +1,37 @@
+//! Feeds random bytes to the frame decoder, with and without compression, and
+//! re-encodes every frame it accepts.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use packet_manager::PacketCodec;
+use tokio_util::codec::{Decoder, Encoder};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&threshold, stream)) = data.split_first() else {
+        return;
+    };
+
+    let mut codec = PacketCodec::new();
+    if threshold & 1 == 1 {
+        codec.set_compression((threshold >> 1) as i32);
+    }
+
+    let mut src = BytesMut::from(stream);
+    while let Ok(Some(frame)) = codec.decode(&mut src) {
+        if frame.is_empty() {
+            continue;
+        }
+
+        // Framing a maximum size packet can push it over the limit, which the
+        // encoder reports rather than panicking.
+        let mut encoded = BytesMut::new();
+        if codec.encode(&frame[..], &mut encoded).is_ok() {
+            assert_eq!(
+                codec.decode(&mut encoded).unwrap().as_deref(),
+                Some(&frame[..])
+            );
+        }
+    }
+});
@@ -0,0 +1,53 @@
+//! Runs the `PacketManager` readers in an order chosen by the input over the
+//! rest of the input. No reader may panic, whatever the data.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use packet_manager::PacketManager;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((ops, packet)) = data.split_first_chunk::<8>() else {
+        return;
+    };
+
+    let mut reader = PacketManager::new(BytesMut::from(packet), 0);
+
+    for op in ops {
+        let before = reader.remaining();
+
+        let ok = match op % 22 {
+            0 => reader.read_boolean().is_ok(),
+            1 => reader.read_byte().is_ok(),
+            2 => reader.read_unsigned_byte().is_ok(),
+            3 => reader.read_short().is_ok(),
+            4 => reader.read_unsigned_short().is_ok(),
+            5 => reader.read_int().is_ok(),
+            6 => reader.read_long().is_ok(),
+            7 => reader.read_float().is_ok(),
+            8 => reader.read_double().is_ok(),
+            9 => reader.read_var_int().is_ok(),
+            10 => reader.read_var_long().is_ok(),
+            11 => reader.read_string().is_ok(),
+            12 => reader.read_bounded_string(16).is_ok(),
+            13 => reader.read_identifier().is_ok(),
+            14 => reader.read_uuid().is_ok(),
+            15 => reader.read_position().is_ok(),
+            16 => reader.read_angle().is_ok(),
+            17 => reader.read_bytes((op / 22) as usize).is_ok(),
+            18 => reader.peek((op / 22) as usize).is_ok(),
+            19 => {
+                reader.read_remaining();
+                true
+            }
+            20 => reader.read_nbt().is_ok(),
+            _ => reader.read_var_int().is_ok(),
+        };
+
+        assert!(reader.remaining() <= before);
+        if !ok {
+            break;
+        }
+    }
+});
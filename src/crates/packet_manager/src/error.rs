@@ -0,0 +1,101 @@
+use std::{io, str::Utf8Error};
+
+use thiserror::Error;
+
+/// Errors raised while reading, writing or dispatching packets.
+///
+/// `field` names the value being read, either a type name such as `VarInt` or,
+/// for derived packets, `Packet.field`.
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("{field} is truncated: needed {needed} bytes but only {remaining} remain")]
+    Truncated {
+        field: &'static str,
+        needed: usize,
+        remaining: usize,
+    },
+
+    #[error("{field} is longer than {max_size} bytes")]
+    VarIntTooLong {
+        field: &'static str,
+        max_size: usize,
+    },
+
+    #[error("{field} is not valid UTF-8: {source}")]
+    InvalidUtf8 {
+        field: &'static str,
+        source: Utf8Error,
+    },
+
+    #[error("{field} is {length} long, the maximum is {max}")]
+    StringTooLong {
+        field: &'static str,
+        length: usize,
+        max: usize,
+    },
+
+    #[error("{field} is invalid: {reason}")]
+    InvalidData { field: &'static str, reason: String },
+
+    #[error("unknown packet {packet_id:#04x} in state {state}")]
+    UnknownPacket { packet_id: i32, state: String },
+
+    #[error("packet {packet_id:#04x} is not expected in state {state}")]
+    BadState { packet_id: i32, state: String },
+
+    #[error("packet {packet} is not part of protocol {protocol}")]
+    Unregistered { packet: &'static str, protocol: i32 },
+
+    #[error("packet {packet_id:#04x}: {source}")]
+    Packet {
+        packet_id: i32,
+        source: Box<ProtocolError>,
+    },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl ProtocolError {
+    /// Replaces the field name of a decoding error, used to report which packet
+    /// field failed rather than the type that was being read.
+    pub fn in_field(mut self, name: &'static str) -> Self {
+        match &mut self {
+            Self::Truncated { field, .. }
+            | Self::VarIntTooLong { field, .. }
+            | Self::InvalidUtf8 { field, .. }
+            | Self::StringTooLong { field, .. }
+            | Self::InvalidData { field, .. } => *field = name,
+            _ => {}
+        }
+        self
+    }
+
+    /// Attaches the id of the packet that failed to decode.
+    pub fn in_packet(self, packet_id: i32) -> Self {
+        match self {
+            Self::Packet { .. }
+            | Self::UnknownPacket { .. }
+            | Self::BadState { .. }
+            | Self::Unregistered { .. }
+            | Self::Io(_) => self,
+            _ => Self::Packet {
+                packet_id,
+                source: Box::new(self),
+            },
+        }
+    }
+
+    /// The reason shown to the client when the connection is closed because of
+    /// this error, or `None` when the connection itself failed or the server
+    /// could not write a packet.
+    pub fn disconnect_reason(&self) -> Option<String> {
+        match self {
+            Self::Io(_) | Self::Unregistered { .. } => None,
+            Self::UnknownPacket { .. } | Self::BadState { .. } => {
+                Some(format!("Unexpected packet: {}", self))
+            }
+            _ => Some(format!("Malformed packet: {}", self)),
+        }
+    }
+}
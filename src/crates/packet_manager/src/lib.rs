@@ -1,7 +1,9 @@
-pub mod packet_handler;
+pub mod error;
+pub mod packet_codec;
 pub mod packet_manager;
 
-pub use packet_handler::PacketHandler;
+pub use error::ProtocolError;
+pub use packet_codec::PacketCodec;
 pub use packet_manager::PacketManager;
 
 pub mod types;
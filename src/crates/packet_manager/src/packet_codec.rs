@@ -0,0 +1,408 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The length prefix of a frame is a VarInt of at most 3 bytes.
+pub const MAX_HEADER_SIZE: usize = 3;
+
+/// Largest frame body (packet id + data) a 3 byte VarInt can describe, 2^21 - 1.
+pub const MAX_PACKET_SIZE: usize = (1 << (7 * MAX_HEADER_SIZE)) - 1;
+
+/// Splits a byte stream into length-prefixed packet frames and prefixes
+/// outgoing packets with their length.
+///
+/// Decoded frames contain the packet id followed by the packet data, without
+/// the length prefix. Partial frames are left in the source buffer until the
+/// rest of the frame has arrived.
+///
+/// Once compression is enabled every frame carries the uncompressed data length
+/// after the packet length, and packets of at least `threshold` bytes are zlib
+/// compressed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PacketCodec {
+    compression_threshold: Option<usize>,
+}
+
+impl PacketCodec {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            compression_threshold: None,
+        }
+    }
+
+    /// Enables compression for packets of at least `threshold` bytes, a negative
+    /// threshold disables compression.
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.compression_threshold = usize::try_from(threshold).ok();
+    }
+
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    /// Reads a VarInt of at most `max_size` bytes at the start of `src` without
+    /// consuming it, returning the value and its encoded size.
+    ///
+    /// Returns `Ok(None)` while the VarInt itself is still incomplete.
+    fn peek_var_int(src: &[u8], max_size: usize) -> Result<Option<(usize, usize)>, Error> {
+        let mut value = 0usize;
+
+        for (i, byte) in src.iter().take(max_size).enumerate() {
+            value |= ((byte & 0x7F) as usize) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(Some((value, i + 1)));
+            }
+        }
+
+        if src.len() >= max_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("VarInt is longer than {} bytes", max_size),
+            ));
+        }
+
+        Ok(None)
+    }
+
+    fn put_var_int(dst: &mut BytesMut, mut value: u32) {
+        while value & !0x7F != 0 {
+            dst.put_u8((value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+        dst.put_u8(value as u8);
+    }
+
+    fn decompress(mut frame: BytesMut, threshold: usize) -> Result<BytesMut, Error> {
+        let Some((data_length, header_size)) = Self::peek_var_int(&frame, 5)? else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Compressed packet is missing its data length",
+            ));
+        };
+        frame.advance(header_size);
+
+        if data_length == 0 {
+            return Ok(frame);
+        }
+
+        if data_length < threshold {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Compressed packet of {} bytes is below the threshold of {}",
+                    data_length, threshold
+                ),
+            ));
+        }
+
+        if data_length > MAX_PACKET_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Compressed packet of {} bytes exceeds the maximum of {} bytes",
+                    data_length, MAX_PACKET_SIZE
+                ),
+            ));
+        }
+
+        // Reading one byte past the announced length is enough to detect a
+        // mismatch without inflating an arbitrarily large payload.
+        let mut data = Vec::with_capacity(data_length);
+        ZlibDecoder::new(&frame[..])
+            .take(data_length as u64 + 1)
+            .read_to_end(&mut data)?;
+
+        if data.len() != data_length {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Compressed packet announced {} bytes but inflated to {}",
+                    data_length,
+                    data.len()
+                ),
+            ));
+        }
+
+        Ok(BytesMut::from(&data[..]))
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = BytesMut;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some((length, header_size)) = Self::peek_var_int(src, MAX_HEADER_SIZE)? else {
+            return Ok(None);
+        };
+
+        if length == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Received an empty packet",
+            ));
+        }
+
+        let frame_size = header_size + length;
+        if src.len() < frame_size {
+            src.reserve(frame_size - src.len());
+            return Ok(None);
+        }
+
+        src.advance(header_size);
+        let frame = src.split_to(length);
+
+        match self.compression_threshold {
+            Some(threshold) => Self::decompress(frame, threshold).map(Some),
+            None => Ok(Some(frame)),
+        }
+    }
+}
+
+impl Encoder<&[u8]> for PacketCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if item.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Cannot send an empty packet",
+            ));
+        }
+
+        let mut body = BytesMut::new();
+        let body = match self.compression_threshold {
+            Some(threshold) if item.len() >= threshold => {
+                Self::put_var_int(&mut body, item.len() as u32);
+                let mut encoder = ZlibEncoder::new(body.writer(), Compression::default());
+                encoder.write_all(item)?;
+                encoder.finish()?.into_inner()
+            }
+            Some(_) => {
+                Self::put_var_int(&mut body, 0);
+                body.extend_from_slice(item);
+                body
+            }
+            None => BytesMut::from(item),
+        };
+
+        if body.len() > MAX_PACKET_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Packet of {} bytes exceeds the maximum of {} bytes",
+                    body.len(),
+                    MAX_PACKET_SIZE
+                ),
+            ));
+        }
+
+        dst.reserve(MAX_HEADER_SIZE + body.len());
+        Self::put_var_int(dst, body.len() as u32);
+        dst.extend_from_slice(&body);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut dst = BytesMut::new();
+        PacketCodec::new().encode(body, &mut dst).unwrap();
+        dst.to_vec()
+    }
+
+    fn decode_all(codec: &mut PacketCodec, src: &mut BytesMut) -> Vec<BytesMut> {
+        let mut frames = Vec::new();
+        while let Some(frame) = codec.decode(src).unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn decodes_a_frame_split_across_many_reads() {
+        let body: Vec<u8> = (0..=255u8).cycle().take(300).collect();
+        let bytes = frame(&body);
+        assert_eq!(&bytes[..2], &[0xAC, 0x02]);
+
+        let mut codec = PacketCodec::new();
+        let mut src = BytesMut::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            src.put_u8(*byte);
+            let decoded = codec.decode(&mut src).unwrap();
+            if i + 1 < bytes.len() {
+                assert!(decoded.is_none(), "frame completed early at byte {}", i);
+            } else {
+                assert_eq!(decoded.unwrap(), &body[..]);
+            }
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn decodes_coalesced_frames() {
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&frame(&[0x00, 0x01]));
+        src.extend_from_slice(&frame(&[0x01]));
+        src.extend_from_slice(&frame(&[0x02, 0x03, 0x04]));
+        // The start of a fourth frame stays buffered.
+        src.extend_from_slice(&[0x05, 0x03]);
+
+        let frames = decode_all(&mut PacketCodec::new(), &mut src);
+        assert_eq!(
+            frames,
+            vec![&[0x00, 0x01][..], &[0x01][..], &[0x02, 0x03, 0x04][..]]
+        );
+        assert_eq!(&src[..], &[0x05, 0x03]);
+    }
+
+    #[test]
+    fn waits_for_an_incomplete_length_prefix() {
+        let mut src = BytesMut::from(&[0x80, 0x80][..]);
+        assert!(PacketCodec::new().decode(&mut src).unwrap().is_none());
+        assert_eq!(src.len(), 2);
+    }
+
+    #[test]
+    fn rejects_a_four_byte_length_prefix() {
+        let mut src = BytesMut::from(&[0x81, 0x80, 0x80, 0x00, 0x00][..]);
+        let error = PacketCodec::new().decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_a_length_above_the_maximum() {
+        // 2^21 needs a fourth VarInt byte.
+        let mut src = BytesMut::from(&[0x80, 0x80, 0x80, 0x01][..]);
+        let error = PacketCodec::new().decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let error = PacketCodec::new()
+            .encode(&vec![0; MAX_PACKET_SIZE + 1][..], &mut BytesMut::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn accepts_the_maximum_length() {
+        let body = vec![0x2A; MAX_PACKET_SIZE];
+        let mut src = BytesMut::from(&frame(&body)[..]);
+        assert_eq!(&src[..3], &[0xFF, 0xFF, 0x7F]);
+
+        let frames = decode_all(&mut PacketCodec::new(), &mut src);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].len(), MAX_PACKET_SIZE);
+    }
+
+    #[test]
+    fn rejects_a_zero_length() {
+        let mut src = BytesMut::from(&[0x00, 0x01][..]);
+        let error = PacketCodec::new().decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let error = PacketCodec::new()
+            .encode(&[][..], &mut BytesMut::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    fn compressed_codec(threshold: i32) -> PacketCodec {
+        let mut codec = PacketCodec::new();
+        codec.set_compression(threshold);
+        codec
+    }
+
+    /// A frame whose data length is `data_length` and whose body is `data` deflated.
+    fn compressed_frame(data_length: u32, data: &[u8]) -> BytesMut {
+        let mut body = BytesMut::new();
+        PacketCodec::put_var_int(&mut body, data_length);
+        let mut encoder = ZlibEncoder::new(body.writer(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let body = encoder.finish().unwrap().into_inner();
+
+        let mut src = BytesMut::new();
+        PacketCodec::put_var_int(&mut src, body.len() as u32);
+        src.extend_from_slice(&body);
+        src
+    }
+
+    #[test]
+    fn round_trips_at_every_threshold() {
+        let packets: Vec<Vec<u8>> = vec![
+            vec![0x00],
+            (0..255u8).collect(),
+            (0..=255u8).collect(),
+            (0..=255u8).cycle().take(5000).collect(),
+            vec![0x2A; 300_000],
+        ];
+
+        for threshold in [-1, 0, 256, 1 << 20] {
+            let mut src = BytesMut::new();
+            let mut codec = compressed_codec(threshold);
+            for packet in &packets {
+                codec.encode(&packet[..], &mut src).unwrap();
+            }
+
+            let frames = decode_all(&mut compressed_codec(threshold), &mut src);
+            assert_eq!(frames, packets, "threshold {}", threshold);
+            assert!(src.is_empty());
+        }
+    }
+
+    #[test]
+    fn compresses_only_packets_at_the_threshold() {
+        let mut codec = compressed_codec(256);
+
+        let mut dst = BytesMut::new();
+        codec.encode(&[0x01; 255][..], &mut dst).unwrap();
+        // Length, then a data length of 0 and the uncompressed packet.
+        assert_eq!(&dst[..3], &[0x80, 0x02, 0x00]);
+        assert_eq!(dst.len(), 2 + 1 + 255);
+
+        let mut dst = BytesMut::new();
+        codec.encode(&[0x01; 256][..], &mut dst).unwrap();
+        let frame = codec.clone().decode(&mut dst.clone()).unwrap().unwrap();
+        assert_eq!(frame, &[0x01; 256][..]);
+        assert!(dst.len() < 256);
+        assert_eq!(&dst[1..3], &[0x80, 0x02]);
+    }
+
+    #[test]
+    fn rejects_compressed_packets_below_the_threshold() {
+        let mut src = compressed_frame(100, &[0x01; 100]);
+        let error = compressed_codec(256).decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut src = compressed_frame(256, &[0x01; 256]);
+        assert!(compressed_codec(256).decode(&mut src).unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_a_data_length_that_does_not_match() {
+        for data_length in [299, 301] {
+            let mut src = compressed_frame(data_length, &[0x01; 300]);
+            let error = compressed_codec(256).decode(&mut src).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_a_data_length_above_the_maximum() {
+        let mut src = compressed_frame(MAX_PACKET_SIZE as u32 + 1, &[0x01; 300]);
+        let error = compressed_codec(256).decode(&mut src).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_corrupt_compressed_data() {
+        let mut src = BytesMut::from(&[0x05, 0xAC, 0x02, 0xDE, 0xAD, 0x00][..]);
+        assert!(compressed_codec(256).decode(&mut src).is_err());
+    }
+}
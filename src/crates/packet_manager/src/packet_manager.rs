@@ -1,10 +1,22 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use log::error;
+use nbt::Blob;
 use num_bigint::BigInt;
 use num_traits::ToPrimitive;
-use tokio::io::AsyncReadExt;
+use uuid::Uuid;
 
-use crate::types::varint_types::VarInt;
+use crate::{
+    types::{
+        nbt_types::{nbt_size, NbtLimits},
+        position_types::Position,
+        varint_types::VarInt,
+        varlong_types::VarLong,
+    },
+    ProtocolError,
+};
+
+/// Longest string the protocol allows, in characters.
+pub const MAX_STRING_LENGTH: usize = 32767;
 
 pub struct PacketManager {
     buffer: BytesMut,
@@ -18,112 +30,193 @@ impl PacketManager {
     }
 
     #[inline(always)]
-    fn ensure_available_bytes(&self, required: usize) -> bool {
-        self.buffer.remaining() >= required
+    fn ensure_available_bytes(
+        &self,
+        required: usize,
+        field: &'static str,
+    ) -> Result<(), ProtocolError> {
+        if self.buffer.remaining() < required {
+            return Err(ProtocolError::Truncated {
+                field,
+                needed: required,
+                remaining: self.buffer.remaining(),
+            });
+        }
+        Ok(())
     }
 
-    pub async fn read_boolean(&mut self) -> bool {
-        self.ensure_available_bytes(1) && self.read_byte().await != 0
+    /// Number of bytes left to read.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.buffer.remaining()
     }
 
-    pub async fn read_byte(&mut self) -> i8 {
-        self.buffer.get_i8()
+    /// Returns the next `len` bytes without consuming them.
+    pub fn peek(&self, len: usize) -> Result<&[u8], ProtocolError> {
+        self.ensure_available_bytes(len, "bytes")?;
+        Ok(&self.buffer[..len])
     }
 
-    pub async fn read_unsigned_byte(&mut self) -> u8 {
-        self.buffer.get_u8()
+    pub fn read_boolean(&mut self) -> Result<bool, ProtocolError> {
+        self.ensure_available_bytes(1, "Boolean")?;
+        Ok(self.buffer.get_u8() != 0)
     }
 
-    pub async fn read_short(&mut self) -> i16 {
-        self.buffer.get_i16()
+    pub fn read_byte(&mut self) -> Result<i8, ProtocolError> {
+        self.ensure_available_bytes(1, "Byte")?;
+        Ok(self.buffer.get_i8())
     }
 
-    pub async fn read_unsigned_short(&mut self) -> u16 {
-        self.buffer.get_u16()
+    pub fn read_unsigned_byte(&mut self) -> Result<u8, ProtocolError> {
+        self.ensure_available_bytes(1, "Unsigned Byte")?;
+        Ok(self.buffer.get_u8())
     }
 
-    pub async fn read_int(&mut self) -> i32 {
-        self.buffer.get_i32()
+    pub fn read_short(&mut self) -> Result<i16, ProtocolError> {
+        self.ensure_available_bytes(2, "Short")?;
+        Ok(self.buffer.get_i16())
     }
 
-    pub async fn read_long(&mut self) -> i64 {
-        self.buffer.get_i64()
+    pub fn read_unsigned_short(&mut self) -> Result<u16, ProtocolError> {
+        self.ensure_available_bytes(2, "Unsigned Short")?;
+        Ok(self.buffer.get_u16())
     }
 
-    pub async fn read_float(&mut self) -> f32 {
-        self.buffer.get_f32()
+    pub fn read_int(&mut self) -> Result<i32, ProtocolError> {
+        self.ensure_available_bytes(4, "Int")?;
+        Ok(self.buffer.get_i32())
     }
 
-    pub async fn read_double(&mut self) -> f64 {
-        self.buffer.get_f64()
+    pub fn read_long(&mut self) -> Result<i64, ProtocolError> {
+        self.ensure_available_bytes(8, "Long")?;
+        Ok(self.buffer.get_i64())
     }
 
-    pub async fn read_var_int<T>(cursor: &mut T) -> Result<VarInt, String>
-    where
-        T: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
-    {
-        let mut result: i32 = 0;
+    pub fn read_float(&mut self) -> Result<f32, ProtocolError> {
+        self.ensure_available_bytes(4, "Float")?;
+        Ok(self.buffer.get_f32())
+    }
 
-        let mut cursor = Box::pin(cursor);
+    pub fn read_double(&mut self) -> Result<f64, ProtocolError> {
+        self.ensure_available_bytes(8, "Double")?;
+        Ok(self.buffer.get_f64())
+    }
 
-        for i in 0..5 {
-            let mut byte = [0u8; 1];
-            cursor
-                .read_exact(&mut byte)
-                .await
-                .map_err(|e| e.to_string())?;
-            let byte = byte[0];
+    pub fn read_var_int(&mut self) -> Result<VarInt, ProtocolError> {
+        let varint = VarInt::from_bytes(&self.buffer)?;
+        self.buffer.advance(varint.len);
+        Ok(varint)
+    }
 
-            result |= ((byte as i32) & 0b01111111) << (i * 7);
-            if byte & 0b10000000 == 0 {
-                return Ok(VarInt {
-                    value: result,
-                    len: i + 1,
-                });
-            }
-        }
+    pub fn read_var_long(&mut self) -> Result<VarLong, ProtocolError> {
+        let varlong = VarLong::from_bytes(&self.buffer)?;
+        self.buffer.advance(varlong.len);
+        Ok(varlong)
+    }
 
-        Err("VarInt too long".to_string())
+    /// Reads `len` bytes without copying them.
+    pub fn read_bytes(&mut self, len: usize) -> Result<Bytes, ProtocolError> {
+        self.ensure_available_bytes(len, "bytes")?;
+        Ok(self.buffer.split_to(len).freeze())
     }
 
-    pub async fn read_var_int_checked(&mut self) -> Option<i32> {
-        let mut value = 0;
-        let mut shift = 0;
+    /// Reads everything left in the packet.
+    pub fn read_remaining(&mut self) -> Bytes {
+        self.buffer.split().freeze()
+    }
+
+    pub fn read_string(&mut self) -> Result<String, ProtocolError> {
+        self.read_bounded_string(MAX_STRING_LENGTH)
+    }
+
+    /// Reads a string of at most `max_length` characters.
+    pub fn read_bounded_string(&mut self, max_length: usize) -> Result<String, ProtocolError> {
+        let length = self.read_var_int().map_err(|e| e.in_field("String"))?;
+        let length = usize::try_from(length.value).map_err(|_| ProtocolError::InvalidData {
+            field: "String",
+            reason: format!("negative length {}", length.value),
+        })?;
 
-        for _ in 0..5 {
-            if self.ensure_available_bytes(1) {
-                let byte = self.read_unsigned_byte().await;
-                value |= ((byte & 0x7F) as i32) << shift;
-                if byte & 0x80 == 0 {
-                    return Some(value);
-                }
-                shift += 7;
-            } else {
-                return None;
-            }
+        // A character takes at most 4 bytes, which rejects oversized strings
+        // before they are validated.
+        if length > max_length * 4 {
+            return Err(ProtocolError::StringTooLong {
+                field: "String",
+                length,
+                max: max_length,
+            });
         }
 
-        None
+        self.ensure_available_bytes(length, "String")?;
+        let slice = self.buffer.split_to(length);
+        let string = std::str::from_utf8(&slice).map_err(|source| ProtocolError::InvalidUtf8 {
+            field: "String",
+            source,
+        })?;
+
+        let chars = string.chars().count();
+        if chars > max_length {
+            return Err(ProtocolError::StringTooLong {
+                field: "String",
+                length: chars,
+                max: max_length,
+            });
+        }
+
+        Ok(string.to_owned())
     }
 
-    pub async fn read_string(&mut self) -> Result<String, String> {
-        let length = self
-            .read_var_int_checked()
-            .await
-            .ok_or_else(|| "Failed to read string length (VarInt)".to_string())?;
+    /// Reads a namespaced identifier such as `minecraft:stone`. Identifiers
+    /// without a namespace are placed in the `minecraft` namespace.
+    pub fn read_identifier(&mut self) -> Result<String, ProtocolError> {
+        let identifier = self.read_string().map_err(|e| e.in_field("Identifier"))?;
 
-        if self.ensure_available_bytes(length as usize) {
-            let slice = self.buffer.split_to(length as usize);
-            String::from_utf8(slice.to_vec()).map_err(|e| e.to_string())
-        } else {
-            Err("Insufficient bytes available to read string".to_string())
+        let (namespace, path) = identifier
+            .split_once(':')
+            .unwrap_or(("minecraft", &identifier));
+
+        let valid_namespace = namespace
+            .bytes()
+            .all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.'));
+        let valid_path = path
+            .bytes()
+            .all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'/'));
+
+        if namespace.is_empty() || path.is_empty() || !valid_namespace || !valid_path {
+            return Err(ProtocolError::InvalidData {
+                field: "Identifier",
+                reason: format!("{:?} is not a valid identifier", identifier),
+            });
         }
+
+        Ok(format!("{}:{}", namespace, path))
+    }
+
+    pub fn read_uuid(&mut self) -> Result<Uuid, ProtocolError> {
+        self.ensure_available_bytes(16, "Uuid")?;
+        Ok(Uuid::from_u128(self.buffer.get_u128()))
+    }
+
+    pub fn read_position(&mut self) -> Result<Position, ProtocolError> {
+        let packed = self.read_long().map_err(|e| e.in_field("Position"))?;
+        Ok(Position::from_packed(packed))
+    }
+
+    /// Reads an angle, sent in steps of 1/256 of a full turn, in degrees.
+    pub fn read_angle(&mut self) -> Result<f32, ProtocolError> {
+        let steps = self.read_unsigned_byte().map_err(|e| e.in_field("Angle"))?;
+        Ok(steps as f32 * 360.0 / 256.0)
     }
 
-    pub async fn read_uuid(&mut self) -> Result<String, String> {
-        let high = self.read_long().await;
-        let low = self.read_long().await;
-        Ok(format!("{:016x}{:016x}", high, low))
+    /// Reads an NBT compound within the default [`NbtLimits`].
+    pub fn read_nbt(&mut self) -> Result<Blob, ProtocolError> {
+        let size = nbt_size(&self.buffer, NbtLimits::default())?;
+        let data = self.buffer.split_to(size);
+
+        Blob::from_reader(&mut &data[..]).map_err(|e| ProtocolError::InvalidData {
+            field: "NBT",
+            reason: e.to_string(),
+        })
     }
 
     pub fn write_boolean(&mut self, value: bool) {
@@ -135,14 +228,8 @@ impl PacketManager {
     }
 
     pub fn write_var_int(&mut self, value: &VarInt) {
-        let value_i32 = value.to_i32();
-
-        let mut value = value_i32;
-        while value & !0x7F != 0 {
-            self.write_unsigned_byte((value & 0x7F) as u8 | 0x80);
-            value >>= 7;
-        }
-        self.write_unsigned_byte(value as u8);
+        let (bytes, len) = value.to_bytes();
+        self.buffer.extend_from_slice(&bytes[..len]);
     }
 
     pub fn write_unsigned_byte(&mut self, value: u8) {
@@ -182,30 +269,37 @@ impl PacketManager {
         self.buffer.extend_from_slice(value.as_bytes());
     }
 
-    pub fn write_var_int_checked(&mut self, mut value: i32) {
-        while value & !0x7F != 0 {
-            self.write_unsigned_byte((value & 0x7F) as u8 | 0x80);
-            value >>= 7;
-        }
-        self.write_unsigned_byte(value as u8);
+    pub fn write_var_int_checked(&mut self, value: i32) {
+        self.write_var_int(&VarInt::new(value));
     }
 
-    pub fn write_var_long(&mut self, mut value: i64) {
-        while value & !0x7F != 0 {
-            self.write_unsigned_byte((value & 0x7F) as u8 | 0x80);
-            value >>= 7;
-        }
-        self.write_unsigned_byte(value as u8);
+    pub fn write_var_long(&mut self, value: i64) {
+        let (bytes, len) = VarLong::new(value).to_bytes();
+        self.buffer.extend_from_slice(&bytes[..len]);
+    }
+
+    pub fn write_nbt(&mut self, value: &Blob) -> Result<(), ProtocolError> {
+        value
+            .to_writer(&mut (&mut self.buffer).writer())
+            .map_err(|e| ProtocolError::InvalidData {
+                field: "NBT",
+                reason: e.to_string(),
+            })
     }
 
-    pub fn write_uuid(&mut self, value: &str) -> Result<(), String> {
+    pub fn write_uuid(&mut self, value: &str) -> Result<(), ProtocolError> {
+        let invalid = || ProtocolError::InvalidData {
+            field: "Uuid",
+            reason: format!("{:?} is not a valid UUID", value),
+        };
+
         let uuid = value.replace("-", "");
         if uuid.len() != 32 {
-            return Err("Invalid UUID format".to_string());
+            return Err(invalid());
         }
 
-        let high = u128::from_str_radix(&uuid[..16], 16).map_err(|_| "Invalid UUID format")?;
-        let low = u128::from_str_radix(&uuid[16..], 16).map_err(|_| "Invalid UUID format")?;
+        let high = u128::from_str_radix(&uuid[..16], 16).map_err(|_| invalid())?;
+        let low = u128::from_str_radix(&uuid[16..], 16).map_err(|_| invalid())?;
         self.write_long((high as i64).into());
         self.write_long((low as i64).into());
         Ok(())
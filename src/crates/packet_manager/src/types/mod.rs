@@ -1 +1,4 @@
+pub mod nbt_types;
+pub mod position_types;
 pub mod varint_types;
+pub mod varlong_types;
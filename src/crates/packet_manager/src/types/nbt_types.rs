@@ -0,0 +1,327 @@
+//! Limits for NBT read from the network.
+//!
+//! NBT stores the length of every list and array up front, so a small packet
+//! can make a parser allocate gigabytes or recurse until the stack overflows.
+//! [`nbt_size`] walks the encoded data without allocating and rejects it before
+//! it reaches the parser.
+
+use crate::ProtocolError;
+
+/// Largest NBT value accepted from a packet, as in vanilla.
+pub const MAX_NBT_SIZE: usize = 2 * 1024 * 1024;
+/// Deepest nesting of lists and compounds accepted from a packet, as in vanilla.
+pub const MAX_NBT_DEPTH: usize = 512;
+/// Memory a decoded value takes on top of its encoded bytes, counted towards
+/// `max_size` so that many tiny values cannot add up to far more than it.
+const VALUE_OVERHEAD: usize = 32;
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NbtLimits {
+    /// Largest accepted size in bytes. Every value also counts
+    /// `VALUE_OVERHEAD` bytes, for the memory it takes once decoded.
+    pub max_size: usize,
+    pub max_depth: usize,
+}
+
+impl NbtLimits {
+    pub fn with_max_size(max_size: usize) -> Self {
+        Self {
+            max_size,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for NbtLimits {
+    fn default() -> Self {
+        Self {
+            max_size: MAX_NBT_SIZE,
+            max_depth: MAX_NBT_DEPTH,
+        }
+    }
+}
+
+/// Returns how many bytes the named root tag at the start of `bytes` takes up,
+/// after checking that it is complete and within `limits`. A root `TAG_End`,
+/// which stands for no value, is one byte long.
+pub fn nbt_size(bytes: &[u8], limits: NbtLimits) -> Result<usize, ProtocolError> {
+    let mut scanner = Scanner {
+        bytes,
+        position: 0,
+        cost: 0,
+        limits,
+    };
+
+    let id = scanner.u8()?;
+    if id != TAG_END {
+        scanner.string()?;
+        scanner.payload(id, 0)?;
+    }
+
+    Ok(scanner.position)
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Bytes read plus the overhead of every value.
+    cost: usize,
+    limits: NbtLimits,
+}
+
+impl Scanner<'_> {
+    fn charge(&mut self, cost: usize) -> Result<(), ProtocolError> {
+        self.cost = self.cost.saturating_add(cost);
+        if self.cost > self.limits.max_size {
+            return Err(ProtocolError::InvalidData {
+                field: "NBT",
+                reason: format!("larger than {} bytes", self.limits.max_size),
+            });
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], ProtocolError> {
+        self.charge(len)?;
+
+        let remaining = self.bytes.len() - self.position;
+        if len > remaining {
+            return Err(ProtocolError::Truncated {
+                field: "NBT",
+                needed: len,
+                remaining,
+            });
+        }
+
+        let data = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads a length, which NBT stores as a signed int.
+    fn length(&mut self) -> Result<usize, ProtocolError> {
+        let data = self.take(4)?;
+        let length = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        usize::try_from(length).map_err(|_| ProtocolError::InvalidData {
+            field: "NBT",
+            reason: format!("negative length {}", length),
+        })
+    }
+
+    fn array(&mut self, element_size: usize) -> Result<(), ProtocolError> {
+        let length = self.length()?;
+        self.take(length.saturating_mul(element_size))?;
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<(), ProtocolError> {
+        let data = self.take(2)?;
+        let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+        self.take(length)?;
+        Ok(())
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> Result<(), ProtocolError> {
+        self.charge(VALUE_OVERHEAD)?;
+
+        match id {
+            TAG_BYTE => self.take(1).map(drop),
+            TAG_SHORT => self.take(2).map(drop),
+            TAG_INT | TAG_FLOAT => self.take(4).map(drop),
+            TAG_LONG | TAG_DOUBLE => self.take(8).map(drop),
+            TAG_BYTE_ARRAY => self.array(1),
+            TAG_INT_ARRAY => self.array(4),
+            TAG_LONG_ARRAY => self.array(8),
+            TAG_STRING => self.string(),
+            TAG_LIST => {
+                self.enter(depth)?;
+                let element = self.u8()?;
+                let length = self.length()?;
+                if element == TAG_END && length > 0 {
+                    return Err(ProtocolError::InvalidData {
+                        field: "NBT",
+                        reason: format!("list of {} end tags", length),
+                    });
+                }
+                for _ in 0..length {
+                    self.payload(element, depth + 1)?;
+                }
+                Ok(())
+            }
+            TAG_COMPOUND => {
+                self.enter(depth)?;
+                loop {
+                    let id = self.u8()?;
+                    if id == TAG_END {
+                        return Ok(());
+                    }
+                    self.string()?;
+                    self.payload(id, depth + 1)?;
+                }
+            }
+            other => Err(ProtocolError::InvalidData {
+                field: "NBT",
+                reason: format!("unknown tag type {}", other),
+            }),
+        }
+    }
+
+    fn enter(&self, depth: usize) -> Result<(), ProtocolError> {
+        if depth >= self.limits.max_depth {
+            return Err(ProtocolError::InvalidData {
+                field: "NBT",
+                reason: format!("nested deeper than {} levels", self.limits.max_depth),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A root tag with an empty name followed by `payload`.
+    fn root(id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![id, 0, 0];
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    /// A list of `depth` lists, each holding the next, around an empty one.
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for _ in 1..depth {
+            payload.extend_from_slice(&[TAG_LIST, 0, 0, 0, 1]);
+        }
+        payload.extend_from_slice(&[TAG_END, 0, 0, 0, 0]);
+        root(TAG_LIST, &payload)
+    }
+
+    fn rejection(bytes: &[u8], limits: NbtLimits) -> String {
+        match nbt_size(bytes, limits) {
+            Err(ProtocolError::InvalidData { reason, .. }) => reason,
+            other => panic!("expected invalid data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn measures_the_root_tag_only() {
+        assert_eq!(nbt_size(&[TAG_END, 0xAA], NbtLimits::default()).unwrap(), 1);
+
+        let mut bytes = root(TAG_COMPOUND, &[TAG_INT, 0, 1, b'a', 0, 0, 0, 7]);
+        bytes.extend_from_slice(&[TAG_LIST, 0, 1, b'b', TAG_END, 0, 0, 0, 0]);
+        bytes.push(TAG_END);
+        let size = bytes.len();
+        bytes.extend_from_slice(&[0xAA, 0xBB]);
+        assert_eq!(nbt_size(&bytes, NbtLimits::default()).unwrap(), size);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = root(TAG_COMPOUND, &[TAG_STRING, 0, 1, b'a', 0, 5, b'a']);
+        assert!(matches!(
+            nbt_size(&bytes, NbtLimits::default()),
+            Err(ProtocolError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_arrays_longer_than_the_limit_before_reading_them() {
+        for id in [TAG_BYTE_ARRAY, TAG_INT_ARRAY, TAG_LONG_ARRAY] {
+            let bytes = root(id, &i32::MAX.to_be_bytes());
+            let reason = rejection(&bytes, NbtLimits::default());
+            assert_eq!(reason, format!("larger than {} bytes", MAX_NBT_SIZE));
+        }
+
+        let bytes = root(TAG_BYTE_ARRAY, &[0, 0, 0, 200]);
+        let reason = rejection(&bytes, NbtLimits::with_max_size(100));
+        assert_eq!(reason, "larger than 100 bytes");
+    }
+
+    #[test]
+    fn counts_the_memory_of_many_small_values() {
+        let length = MAX_NBT_SIZE / VALUE_OVERHEAD;
+        let mut payload = vec![TAG_BYTE];
+        payload.extend_from_slice(&(length as i32).to_be_bytes());
+        payload.resize(payload.len() + length, 0);
+        let bytes = root(TAG_LIST, &payload);
+        assert!(bytes.len() < MAX_NBT_SIZE);
+
+        let reason = rejection(&bytes, NbtLimits::default());
+        assert_eq!(reason, format!("larger than {} bytes", MAX_NBT_SIZE));
+    }
+
+    #[test]
+    fn rejects_nesting_deeper_than_the_limit() {
+        let bytes = nested_lists(MAX_NBT_DEPTH);
+        assert_eq!(nbt_size(&bytes, NbtLimits::default()).unwrap(), bytes.len());
+
+        let reason = rejection(&nested_lists(MAX_NBT_DEPTH + 1), NbtLimits::default());
+        assert_eq!(
+            reason,
+            format!("nested deeper than {} levels", MAX_NBT_DEPTH)
+        );
+
+        let mut bytes = root(TAG_COMPOUND, &[]);
+        for _ in 0..MAX_NBT_DEPTH {
+            bytes.extend_from_slice(&[TAG_COMPOUND, 0, 0]);
+        }
+        let reason = rejection(&bytes, NbtLimits::default());
+        assert_eq!(
+            reason,
+            format!("nested deeper than {} levels", MAX_NBT_DEPTH)
+        );
+    }
+
+    #[test]
+    fn rejects_negative_lengths() {
+        let bytes = root(TAG_LIST, &[TAG_BYTE, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(
+            rejection(&bytes, NbtLimits::default()),
+            "negative length -1"
+        );
+
+        let bytes = root(TAG_INT_ARRAY, &i32::MIN.to_be_bytes());
+        assert_eq!(
+            rejection(&bytes, NbtLimits::default()),
+            format!("negative length {}", i32::MIN)
+        );
+    }
+
+    #[test]
+    fn rejects_lists_of_end_tags() {
+        let bytes = root(TAG_LIST, &[TAG_END, 0, 0, 0, 3]);
+        assert_eq!(
+            rejection(&bytes, NbtLimits::default()),
+            "list of 3 end tags"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_tag_types() {
+        let bytes = root(TAG_COMPOUND, &[13, 0, 0]);
+        assert_eq!(
+            rejection(&bytes, NbtLimits::default()),
+            "unknown tag type 13"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::Display;
+
+/// A block position, sent as a single 64 bit integer holding a 26 bit x, a 26
+/// bit z and a 12 bit y coordinate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Unpacks a position, sign extending each coordinate.
+    pub fn from_packed(value: i64) -> Self {
+        Self {
+            x: (value >> 38) as i32,
+            y: (value << 52 >> 52) as i32,
+            z: (value << 26 >> 38) as i32,
+        }
+    }
+
+    /// Packs the position, coordinates outside the representable range wrap.
+    pub fn to_packed(&self) -> i64 {
+        ((self.x as i64 & 0x3FF_FFFF) << 38)
+            | ((self.z as i64 & 0x3FF_FFFF) << 12)
+            | (self.y as i64 & 0xFFF)
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
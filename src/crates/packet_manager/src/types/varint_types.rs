@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use crate::ProtocolError;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct VarInt {
     pub value: i32,
@@ -7,16 +9,24 @@ pub struct VarInt {
 }
 
 impl VarInt {
+    /// A VarInt never takes more than 5 bytes.
+    pub const MAX_SIZE: usize = 5;
+
     pub fn new(value: i32) -> Self {
-        let len = match value {
-            -128..=127 => 1,
-            -16_384..=16_383 => 2,
-            -2_097_152..=2_097_151 => 3,
-            -268_435_456..=268_435_455 => 4,
-            _ => 5,
-        };
+        Self {
+            value,
+            len: Self::encoded_len(value),
+        }
+    }
 
-        Self { value, len }
+    /// Number of bytes `value` takes when encoded. Negative values always take 5 bytes.
+    pub const fn encoded_len(value: i32) -> usize {
+        let bits = 32 - (value as u32).leading_zeros() as usize;
+        if bits == 0 {
+            1
+        } else {
+            bits.div_ceil(7)
+        }
     }
 
     pub fn to_i32(&self) -> i32 {
@@ -27,14 +37,49 @@ impl VarInt {
         self.len
     }
 
-    pub fn to_be_bytes(&self) -> [u8; 5] {
-        let mut buf = [0u8; 5];
-        buf[0] = (self.value & 0x000000FF) as u8;
-        buf[1] = ((self.value & 0x0000FF00) >> 8) as u8;
-        buf[2] = ((self.value & 0x00FF0000) >> 16) as u8;
-        buf[3] = ((self.value & 0xFF000000u32 as i32) >> 24) as u8;
-        buf[4] = ((self.value & 0xFF000000u32 as i32) >> 24) as u8;
-        buf
+    /// Encodes the value as LEB128, returning the buffer and the number of bytes used.
+    pub fn to_bytes(&self) -> ([u8; Self::MAX_SIZE], usize) {
+        let mut buf = [0u8; Self::MAX_SIZE];
+        let mut value = self.value as u32;
+        let mut i = 0;
+
+        while value & !0x7F != 0 {
+            buf[i] = (value & 0x7F) as u8 | 0x80;
+            value >>= 7;
+            i += 1;
+        }
+        buf[i] = value as u8;
+
+        (buf, i + 1)
+    }
+
+    /// Decodes a VarInt from the start of `bytes`. `len` of the result is the
+    /// number of bytes consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let mut value: u32 = 0;
+
+        for (i, byte) in bytes.iter().take(Self::MAX_SIZE).enumerate() {
+            value |= ((byte & 0x7F) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(Self {
+                    value: value as i32,
+                    len: i + 1,
+                });
+            }
+        }
+
+        if bytes.len() >= Self::MAX_SIZE {
+            Err(ProtocolError::VarIntTooLong {
+                field: "VarInt",
+                max_size: Self::MAX_SIZE,
+            })
+        } else {
+            Err(ProtocolError::Truncated {
+                field: "VarInt",
+                needed: bytes.len() + 1,
+                remaining: bytes.len(),
+            })
+        }
     }
 }
 
@@ -56,8 +101,127 @@ impl From<i32> for VarInt {
     }
 }
 
-impl Into<usize> for VarInt {
-    fn into(self) -> usize {
-        self.value as usize
+impl From<VarInt> for usize {
+    fn from(varint: VarInt) -> usize {
+        varint.value as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Encodes `value` the way the protocol documentation describes it, one
+    /// group of 7 bits at a time, lowest first.
+    fn reference_encode(value: i32) -> Vec<u8> {
+        let mut value = value as u32 as u64;
+        let mut bytes = Vec::new();
+        loop {
+            let group = (value % 128) as u8;
+            value /= 128;
+            if value == 0 {
+                bytes.push(group);
+                return bytes;
+            }
+            bytes.push(group + 128);
+        }
+    }
+
+    fn encode(value: i32) -> Vec<u8> {
+        let (buf, len) = VarInt::new(value).to_bytes();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn encodes_known_values() {
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (2, &[0x02]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xFF, 0x01]),
+            (25565, &[0xDD, 0xC7, 0x01]),
+            (2097151, &[0xFF, 0xFF, 0x7F]),
+            (i32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+            (-1, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+            (i32::MIN, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        ];
+
+        for (value, bytes) in cases {
+            assert_eq!(encode(*value), *bytes, "encoding {}", value);
+            assert_eq!(
+                VarInt::encoded_len(*value),
+                bytes.len(),
+                "length of {}",
+                value
+            );
+
+            let decoded = VarInt::from_bytes(bytes).unwrap();
+            assert_eq!(decoded.value, *value);
+            assert_eq!(decoded.len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn changes_length_at_each_7_bit_boundary() {
+        for bytes in 1..VarInt::MAX_SIZE {
+            let largest = (1i64 << (7 * bytes)) - 1;
+            assert_eq!(VarInt::encoded_len(largest as i32), bytes);
+            assert_eq!(VarInt::encoded_len(largest as i32 + 1), bytes + 1);
+            assert_eq!(encode(largest as i32).len(), bytes);
+            assert_eq!(encode(largest as i32 + 1).len(), bytes + 1);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_first_byte_without_a_continuation_bit() {
+        let decoded = VarInt::from_bytes(&[0xAC, 0x02, 0xFF, 0xFF]).unwrap();
+        assert_eq!(decoded.value, 300);
+        assert_eq!(decoded.len, 2);
+    }
+
+    #[test]
+    fn rejects_a_6_byte_varint() {
+        let error = VarInt::from_bytes(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]).unwrap_err();
+        assert!(matches!(
+            error,
+            ProtocolError::VarIntTooLong { max_size: 5, .. }
+        ));
+    }
+
+    #[test]
+    fn reports_a_truncated_varint() {
+        for bytes in [&[][..], &[0x80], &[0xFF, 0xFF, 0xFF, 0xFF]] {
+            let error = VarInt::from_bytes(bytes).unwrap_err();
+            assert!(matches!(
+                error,
+                ProtocolError::Truncated { needed, remaining, .. }
+                    if remaining == bytes.len() && needed == bytes.len() + 1
+            ));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn matches_the_reference(value: i32) {
+            let expected = reference_encode(value);
+            prop_assert_eq!(encode(value), expected.clone());
+            prop_assert_eq!(VarInt::encoded_len(value), expected.len());
+            prop_assert_eq!(VarInt::new(value).len, expected.len());
+        }
+
+        #[test]
+        fn round_trips(value: i32, trailing in proptest::collection::vec(any::<u8>(), 0..8)) {
+            let mut bytes = encode(value);
+            let len = bytes.len();
+            bytes.extend(trailing);
+
+            let decoded = VarInt::from_bytes(&bytes).unwrap();
+            prop_assert_eq!(decoded.value, value);
+            prop_assert_eq!(decoded.len, len);
+        }
     }
 }
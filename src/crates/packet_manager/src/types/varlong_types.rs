@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use crate::ProtocolError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VarLong {
+    pub value: i64,
+    pub len: usize,
+}
+
+impl VarLong {
+    /// A VarLong never takes more than 10 bytes.
+    pub const MAX_SIZE: usize = 10;
+
+    pub fn new(value: i64) -> Self {
+        Self {
+            value,
+            len: Self::encoded_len(value),
+        }
+    }
+
+    /// Number of bytes `value` takes when encoded. Negative values always take 10 bytes.
+    pub const fn encoded_len(value: i64) -> usize {
+        let bits = 64 - (value as u64).leading_zeros() as usize;
+        if bits == 0 {
+            1
+        } else {
+            bits.div_ceil(7)
+        }
+    }
+
+    pub fn to_i64(&self) -> i64 {
+        self.value
+    }
+
+    pub fn length(&self) -> usize {
+        self.len
+    }
+
+    /// Encodes the value as LEB128, returning the buffer and the number of bytes used.
+    pub fn to_bytes(&self) -> ([u8; Self::MAX_SIZE], usize) {
+        let mut buf = [0u8; Self::MAX_SIZE];
+        let mut value = self.value as u64;
+        let mut i = 0;
+
+        while value & !0x7F != 0 {
+            buf[i] = (value & 0x7F) as u8 | 0x80;
+            value >>= 7;
+            i += 1;
+        }
+        buf[i] = value as u8;
+
+        (buf, i + 1)
+    }
+
+    /// Decodes a VarLong from the start of `bytes`. `len` of the result is the
+    /// number of bytes consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let mut value: u64 = 0;
+
+        for (i, byte) in bytes.iter().take(Self::MAX_SIZE).enumerate() {
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(Self {
+                    value: value as i64,
+                    len: i + 1,
+                });
+            }
+        }
+
+        if bytes.len() >= Self::MAX_SIZE {
+            Err(ProtocolError::VarIntTooLong {
+                field: "VarLong",
+                max_size: Self::MAX_SIZE,
+            })
+        } else {
+            Err(ProtocolError::Truncated {
+                field: "VarLong",
+                needed: bytes.len() + 1,
+                remaining: bytes.len(),
+            })
+        }
+    }
+}
+
+impl Display for VarLong {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl From<VarLong> for i64 {
+    fn from(varlong: VarLong) -> i64 {
+        varlong.value
+    }
+}
+
+impl From<i64> for VarLong {
+    fn from(value: i64) -> VarLong {
+        VarLong::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Encodes `value` the way the protocol documentation describes it, one
+    /// group of 7 bits at a time, lowest first.
+    fn reference_encode(value: i64) -> Vec<u8> {
+        let mut value = value as u64 as u128;
+        let mut bytes = Vec::new();
+        loop {
+            let group = (value % 128) as u8;
+            value /= 128;
+            if value == 0 {
+                bytes.push(group);
+                return bytes;
+            }
+            bytes.push(group + 128);
+        }
+    }
+
+    fn encode(value: i64) -> Vec<u8> {
+        let (buf, len) = VarLong::new(value).to_bytes();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn encodes_known_values() {
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (i32::MAX as i64, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+            (
+                i64::MAX,
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F],
+            ),
+            (
+                -1,
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            ),
+            (
+                i32::MIN as i64,
+                &[0x80, 0x80, 0x80, 0x80, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            ),
+            (
+                i64::MIN,
+                &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+            ),
+        ];
+
+        for (value, bytes) in cases {
+            assert_eq!(encode(*value), *bytes, "encoding {}", value);
+            assert_eq!(
+                VarLong::encoded_len(*value),
+                bytes.len(),
+                "length of {}",
+                value
+            );
+
+            let decoded = VarLong::from_bytes(bytes).unwrap();
+            assert_eq!(decoded.value, *value);
+            assert_eq!(decoded.len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn changes_length_at_each_7_bit_boundary() {
+        for bytes in 1..VarLong::MAX_SIZE {
+            let largest = ((1i128 << (7 * bytes)) - 1) as i64;
+            assert_eq!(VarLong::encoded_len(largest), bytes);
+            assert_eq!(encode(largest).len(), bytes);
+            if bytes < 9 {
+                assert_eq!(VarLong::encoded_len(largest + 1), bytes + 1);
+                assert_eq!(encode(largest + 1).len(), bytes + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_an_11_byte_varlong() {
+        let mut bytes = vec![0x80; 10];
+        bytes.push(0x01);
+        let error = VarLong::from_bytes(&bytes).unwrap_err();
+        assert!(matches!(
+            error,
+            ProtocolError::VarIntTooLong { max_size: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn reports_a_truncated_varlong() {
+        let bytes = [0xFF; 9];
+        let error = VarLong::from_bytes(&bytes).unwrap_err();
+        assert!(matches!(
+            error,
+            ProtocolError::Truncated {
+                needed: 10,
+                remaining: 9,
+                ..
+            }
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn matches_the_reference(value: i64) {
+            let expected = reference_encode(value);
+            prop_assert_eq!(encode(value), expected.clone());
+            prop_assert_eq!(VarLong::encoded_len(value), expected.len());
+        }
+
+        #[test]
+        fn round_trips(value: i64, trailing in proptest::collection::vec(any::<u8>(), 0..8)) {
+            let mut bytes = encode(value);
+            let len = bytes.len();
+            bytes.extend(trailing);
+
+            let decoded = VarLong::from_bytes(&bytes).unwrap();
+            prop_assert_eq!(decoded.value, value);
+            prop_assert_eq!(decoded.len, len);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    Attribute, DeriveInput, Error, Expr, ExprLit, Field, GenericParam, Ident, Lit, LitInt, Result,
+    Type,
+};
+
+const STATES: [&str; 4] = ["Handshake", "Status", "Login", "Play"];
+const DIRECTIONS: [&str; 2] = ["Serverbound", "Clientbound"];
+
+/// `#[packet(id = 0x26, state = Play, direction = Clientbound)]`
+pub struct PacketAttribute {
+    pub id: i32,
+    pub state: Ident,
+    pub direction: Ident,
+}
+
+pub fn packet_attribute(attrs: &[Attribute]) -> Result<Option<PacketAttribute>> {
+    let mut packet = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("packet")) {
+        if packet.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate #[packet] attribute"));
+        }
+
+        let mut id = None;
+        let mut state = None;
+        let mut direction = None;
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                let lit: LitInt = meta.value()?.parse()?;
+                id = Some(lit.base10_parse::<i32>()?);
+            } else if meta.path.is_ident("state") {
+                state = Some(one_of(meta.value()?.parse()?, &STATES, "state")?);
+            } else if meta.path.is_ident("direction") {
+                direction = Some(one_of(meta.value()?.parse()?, &DIRECTIONS, "direction")?);
+            } else {
+                return Err(
+                    meta.error("unknown #[packet] option, expected `id`, `state` or `direction`")
+                );
+            }
+            Ok(())
+        })?;
+
+        let missing =
+            |name: &str| Error::new_spanned(attr, format!("#[packet] is missing `{}`", name));
+        packet = Some(PacketAttribute {
+            id: id.ok_or_else(|| missing("id"))?,
+            state: state.ok_or_else(|| missing("state"))?,
+            direction: direction.ok_or_else(|| missing("direction"))?,
+        });
+    }
+
+    Ok(packet)
+}
+
+fn one_of(ident: Ident, allowed: &[&str], what: &str) -> Result<Ident> {
+    if !allowed.iter().any(|name| ident == name) {
+        return Err(Error::new(
+            ident.span(),
+            format!(
+                "unknown {} `{}`, expected one of {}",
+                what,
+                ident,
+                allowed.join(", ")
+            ),
+        ));
+    }
+    Ok(ident)
+}
+
+/// A compile time check of the `#[packet]` attribute of `input`, if it has
+/// one, against the `Packet` impl the registry generates for the type.
+pub fn packet_check(input: &DeriveInput) -> Result<TokenStream> {
+    let Some(packet) = packet_attribute(&input.attrs)? else {
+        return Ok(quote!());
+    };
+
+    // The check runs in a constant, which cannot name type parameters.
+    let mut lifetimes = Vec::new();
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(_) => lifetimes.push(quote!('static)),
+            _ => {
+                return Err(Error::new_spanned(
+                    param,
+                    "#[packet] cannot be used on types with type or const parameters",
+                ))
+            }
+        }
+    }
+
+    let name = &input.ident;
+    let ty = if lifetimes.is_empty() {
+        quote!(#name)
+    } else {
+        quote!(#name<#(#lifetimes),*>)
+    };
+    let id = packet.id;
+    let state = &packet.state;
+    let direction = &packet.direction;
+
+    Ok(quote! {
+        const _: () = ::pandora_utils::types::packet_types::check_packet_attribute::<#ty>(
+            #id,
+            ::pandora_utils::types::packet_types::PacketState::#state,
+            ::pandora_utils::types::packet_types::PacketDirection::#direction,
+        );
+    })
+}
+
+/// Per-field options. Each flag keeps the span of its attribute for errors.
+#[derive(Default)]
+pub struct FieldOptions {
+    pub varint: Option<Span>,
+    pub max_len: Option<(usize, Span)>,
+    pub length_prefixed: Option<Span>,
+    pub rest: Option<Span>,
+    pub optional: Option<Span>,
+    pub skip: Option<Span>,
+    pub nbt: Option<Span>,
+    /// Size limit in bytes of an `#[nbt(max_size = N)]` field.
+    pub nbt_max_size: Option<usize>,
+}
+
+impl FieldOptions {
+    pub fn parse(field: &Field) -> Result<Self> {
+        let mut options = FieldOptions::default();
+
+        for attr in &field.attrs {
+            let Some(name) = attr.path().get_ident().map(Ident::to_string) else {
+                continue;
+            };
+            let span = attr.path().get_ident().unwrap().span();
+
+            let slot = match name.as_str() {
+                "varint" => &mut options.varint,
+                "length_prefixed" => &mut options.length_prefixed,
+                "rest" => &mut options.rest,
+                "skip" => &mut options.skip,
+                "optional" => {
+                    let mut bool_prefix = false;
+                    attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("bool_prefix") {
+                            bool_prefix = true;
+                            Ok(())
+                        } else {
+                            Err(meta.error("unknown optional encoding, expected `bool_prefix`"))
+                        }
+                    })?;
+                    if !bool_prefix {
+                        return Err(Error::new_spanned(
+                            attr,
+                            "expected #[optional(bool_prefix)]",
+                        ));
+                    }
+                    if !is_option(&field.ty) {
+                        return Err(Error::new_spanned(
+                            &field.ty,
+                            "#[optional] can only be used on Option<T> fields",
+                        ));
+                    }
+                    &mut options.optional
+                }
+                "nbt" => {
+                    if let syn::Meta::List(_) = attr.meta {
+                        attr.parse_nested_meta(|meta| {
+                            if meta.path.is_ident("max_size") {
+                                let lit: syn::LitInt = meta.value()?.parse()?;
+                                options.nbt_max_size = Some(lit.base10_parse()?);
+                                Ok(())
+                            } else {
+                                Err(meta.error("unknown nbt option, expected `max_size`"))
+                            }
+                        })?;
+                    }
+                    &mut options.nbt
+                }
+                "max_len" => {
+                    let value = &attr.meta.require_name_value()?.value;
+                    let max_len = match value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Int(lit), ..
+                        }) => lit.base10_parse::<usize>()?,
+                        other => {
+                            return Err(Error::new_spanned(
+                                other,
+                                "expected an integer, as in #[max_len = 16]",
+                            ))
+                        }
+                    };
+                    if options.max_len.replace((max_len, span)).is_some() {
+                        return Err(Error::new_spanned(attr, "duplicate #[max_len] attribute"));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if name != "optional" && name != "nbt" {
+                attr.meta.require_path_only()?;
+            }
+            if slot.replace(span).is_some() {
+                return Err(Error::new_spanned(
+                    attr,
+                    format!("duplicate #[{}] attribute", name),
+                ));
+            }
+        }
+
+        options.validate()?;
+        Ok(options)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let max_len = self.max_len.map(|(_, span)| span);
+        let set = [
+            ("skip", self.skip),
+            ("rest", self.rest),
+            ("varint", self.varint),
+            ("length_prefixed", self.length_prefixed),
+            ("optional", self.optional),
+            ("max_len", max_len),
+            ("nbt", self.nbt),
+        ];
+
+        let conflicts: &[(&str, &[&str])] = &[
+            (
+                "skip",
+                &[
+                    "rest",
+                    "varint",
+                    "length_prefixed",
+                    "optional",
+                    "max_len",
+                    "nbt",
+                ],
+            ),
+            (
+                "rest",
+                &["varint", "length_prefixed", "optional", "max_len", "nbt"],
+            ),
+            ("varint", &["length_prefixed", "optional", "max_len", "nbt"]),
+            ("optional", &["length_prefixed", "max_len", "nbt"]),
+            ("nbt", &["length_prefixed", "max_len"]),
+        ];
+
+        let get = |name: &str| {
+            set.iter()
+                .find(|(n, _)| *n == name)
+                .and_then(|(_, span)| *span)
+        };
+        for (name, others) in conflicts {
+            if get(name).is_none() {
+                continue;
+            }
+            if let Some(other) = others.iter().find(|other| get(other).is_some()) {
+                return Err(Error::new(
+                    get(other).unwrap(),
+                    format!("#[{}] cannot be combined with #[{}]", other, name),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// VarInt discriminants of the variants of an enum. Variants take their id from
+/// `= N` or `#[id = N]`, or one more than the previous variant.
+pub fn variant_ids(data: &syn::DataEnum) -> Result<Vec<i32>> {
+    let mut ids: Vec<i32> = Vec::new();
+    let mut next_id = 0;
+
+    for variant in &data.variants {
+        let id = variant_id(variant)?.unwrap_or(next_id);
+        if ids.contains(&id) {
+            return Err(Error::new_spanned(
+                variant,
+                format!("duplicate variant id {}", id),
+            ));
+        }
+
+        ids.push(id);
+        next_id = id.wrapping_add(1);
+    }
+
+    Ok(ids)
+}
+
+fn variant_id(variant: &syn::Variant) -> Result<Option<i32>> {
+    let mut id = None;
+
+    if let Some((_, expr)) = &variant.discriminant {
+        id = Some(int_literal(expr)?);
+    }
+
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("id"))
+    {
+        if id.is_some() {
+            return Err(Error::new_spanned(attr, "variant already has an id"));
+        }
+        id = Some(int_literal(&attr.meta.require_name_value()?.value)?);
+    }
+
+    Ok(id)
+}
+
+fn int_literal(expr: &Expr) -> Result<i32> {
+    let (negative, expr) = match expr {
+        Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => (true, expr.as_ref()),
+        expr => (false, expr),
+    };
+
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => {
+            let value = lit.base10_parse::<i32>()?;
+            Ok(if negative { -value } else { value })
+        }
+        other => Err(Error::new_spanned(other, "expected an integer literal")),
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
@@ -0,0 +1,143 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput, Error, GenericParam, Lifetime, LifetimeParam, Result};
+
+use crate::{
+    attributes::{packet_check, variant_ids},
+    fields::{collect, construct, FieldInfo},
+};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let packet_check = packet_check(input)?;
+    let name = &input.ident;
+
+    // Borrowed fields tie the packet to the lifetime of the buffer it was read
+    // from, types without one get a fresh lifetime for the impl.
+    let mut impl_generics = input.generics.clone();
+    let lifetime = match input.generics.lifetimes().next() {
+        Some(param) => param.lifetime.clone(),
+        None => {
+            let lifetime = Lifetime::new("'__bytes", Span::call_site());
+            impl_generics.params.insert(
+                0,
+                GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())),
+            );
+            lifetime
+        }
+    };
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let infos = collect(&data.fields, &name.to_string())?;
+            let statements = infos.iter().map(|info| decode_field(info, &lifetime));
+            let value = construct(&quote!(Self), &data.fields, &infos);
+
+            quote! {
+                #(#statements)*
+                Ok(#value)
+            }
+        }
+        Data::Enum(data) => {
+            let enum_name = name.to_string();
+            let mut arms = Vec::new();
+
+            for (variant, id) in data.variants.iter().zip(variant_ids(data)?) {
+                let ident = &variant.ident;
+                let infos = collect(&variant.fields, &format!("{}::{}", name, ident))?;
+                let statements = infos.iter().map(|info| decode_field(info, &lifetime));
+                let value = construct(&quote!(Self::#ident), &variant.fields, &infos);
+
+                arms.push(quote! {
+                    #id => {
+                        #(#statements)*
+                        Ok(#value)
+                    }
+                });
+            }
+
+            quote! {
+                let id = <i32 as ::pandora_utils::types::field_types::VarIntField>::decode_var(bytes)
+                    .map_err(|e| e.in_field(#enum_name))?;
+
+                match id {
+                    #(#arms)*
+                    other => Err(::pandora_utils::types::decode_types::ProtocolError::InvalidData {
+                        field: #enum_name,
+                        reason: format!("unknown variant id {}", other),
+                    }),
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                input,
+                "Decode cannot be derived for unions",
+            ))
+        }
+    };
+
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        #packet_check
+
+        impl #impl_generics ::pandora_utils::types::decode_types::Decode<#lifetime> for #name #ty_generics #where_clause {
+            fn decode(bytes: &mut &#lifetime [u8]) -> Result<Self, ::pandora_utils::types::decode_types::ProtocolError> {
+                #body
+            }
+        }
+    })
+}
+
+fn decode_field(info: &FieldInfo, lifetime: &Lifetime) -> TokenStream {
+    let binding = &info.binding;
+    let ty = &info.ty;
+    let name = &info.name;
+    let options = &info.options;
+
+    let value = if options.skip.is_some() {
+        quote!(::core::default::Default::default())
+    } else if options.varint.is_some() {
+        quote! {
+            <#ty as ::pandora_utils::types::field_types::VarIntField>::decode_var(bytes)
+                .map_err(|e| e.in_field(#name))?
+        }
+    } else if options.rest.is_some() {
+        quote! {
+            <#ty as ::pandora_utils::types::field_types::RestField<#lifetime>>::decode_rest(bytes)
+        }
+    } else if options.nbt.is_some() {
+        let limits = match options.nbt_max_size {
+            Some(max_size) => quote! {
+                ::pandora_utils::types::nbt_types::NbtLimits::with_max_size(#max_size)
+            },
+            None => quote!(::pandora_utils::types::nbt_types::NbtLimits::default()),
+        };
+        quote! {
+            ::pandora_utils::types::nbt_types::decode_nbt::<#ty>(bytes, #limits)
+                .map_err(|e| e.in_field(#name))?
+        }
+    } else if options.length_prefixed.is_some() {
+        quote! {
+            ::pandora_utils::types::field_types::decode_length_prefixed::<#ty>(bytes)
+                .map_err(|e| e.in_field(#name))?
+        }
+    } else {
+        quote! {
+            <#ty as ::pandora_utils::types::decode_types::Decode<#lifetime>>::decode(bytes)
+                .map_err(|e| e.in_field(#name))?
+        }
+    };
+
+    let check = options.max_len.map(|(max_len, _)| {
+        quote! {
+            ::pandora_utils::types::field_types::check_max_len(&#binding, #max_len, #name)?;
+        }
+    });
+
+    quote! {
+        let #binding: #ty = #value;
+        #check
+    }
+}
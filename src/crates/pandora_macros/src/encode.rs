@@ -0,0 +1,117 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Result};
+
+use crate::{
+    attributes::{packet_check, variant_ids},
+    fields::{collect, pattern, FieldInfo},
+};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let packet_check = packet_check(input)?;
+    let name = &input.ident;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let infos = collect(&data.fields, &name.to_string())?;
+            let pattern = pattern(&quote!(Self), &data.fields, &infos);
+            let statements = infos.iter().map(encode_field);
+
+            quote! {
+                let #pattern = self;
+                #(#statements)*
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+
+            for (variant, id) in data.variants.iter().zip(variant_ids(data)?) {
+                let ident = &variant.ident;
+                let infos = collect(&variant.fields, &format!("{}::{}", name, ident))?;
+                let pattern = pattern(&quote!(Self::#ident), &variant.fields, &infos);
+                let statements = infos.iter().map(encode_field);
+
+                arms.push(quote! {
+                    #pattern => {
+                        <i32 as ::pandora_utils::types::field_types::VarIntField>::encode_var(&#id, bytes)?;
+                        #(#statements)*
+                    }
+                });
+            }
+
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                input,
+                "Encode cannot be derived for unions",
+            ))
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        #packet_check
+
+        impl #impl_generics ::pandora_utils::types::encode_types::Encode for #name #ty_generics #where_clause {
+            fn encode<B>(&self, bytes: &mut B) -> Result<(), std::io::Error>
+            where
+                B: ::pandora_utils::types::encode_types::BufMut,
+            {
+                #body
+                Ok(())
+            }
+        }
+    })
+}
+
+fn encode_field(info: &FieldInfo) -> TokenStream {
+    let binding = &info.binding;
+    let options = &info.options;
+
+    if options.skip.is_some() {
+        return quote!();
+    }
+
+    if options.varint.is_some() {
+        return quote! {
+            ::pandora_utils::types::field_types::VarIntField::encode_var(#binding, bytes)?;
+        };
+    }
+
+    if options.rest.is_some() {
+        return quote! {
+            ::pandora_utils::types::field_types::RestField::encode_rest(#binding, bytes);
+        };
+    }
+
+    if options.nbt.is_some() {
+        return quote! {
+            ::pandora_utils::types::nbt_types::encode_nbt(#binding, bytes)?;
+        };
+    }
+
+    if options.length_prefixed.is_some() {
+        return quote! {
+            ::pandora_utils::types::field_types::encode_length_prefixed(#binding, bytes)?;
+        };
+    }
+
+    let check = options.max_len.map(|(max_len, _)| {
+        let name = &info.name;
+        quote! {
+            ::pandora_utils::types::field_types::check_max_len(#binding, #max_len, #name)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        }
+    });
+
+    quote! {
+        #check
+        ::pandora_utils::types::encode_types::Encode::encode(#binding, bytes)?;
+    }
+}
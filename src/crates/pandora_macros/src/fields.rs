@@ -0,0 +1,92 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Fields, Ident, Member, Result, Type};
+
+use crate::attributes::FieldOptions;
+
+pub struct FieldInfo {
+    pub member: Member,
+    /// Local variable the field is bound to while encoding or decoding.
+    pub binding: Ident,
+    pub ty: Type,
+    pub options: FieldOptions,
+    /// Name used in error messages, such as `HandshakePacket.server_address`.
+    pub name: String,
+}
+
+pub fn collect(fields: &Fields, owner: &str) -> Result<Vec<FieldInfo>> {
+    let mut infos = Vec::new();
+
+    for (index, field) in fields.iter().enumerate() {
+        let (member, binding, field_name) = match &field.ident {
+            Some(ident) => (
+                Member::Named(ident.clone()),
+                format_ident!("__field_{}", ident),
+                ident.to_string(),
+            ),
+            None => (
+                Member::Unnamed(index.into()),
+                format_ident!("__field_{}", index),
+                index.to_string(),
+            ),
+        };
+
+        infos.push(FieldInfo {
+            member,
+            binding,
+            ty: field.ty.clone(),
+            options: FieldOptions::parse(field)?,
+            name: format!("{}.{}", owner, field_name),
+        });
+    }
+
+    // Skipped fields are not on the wire, so they may follow a #[rest] field.
+    let mut on_wire = infos.iter().filter(|info| info.options.skip.is_none());
+    while let Some(info) = on_wire.next() {
+        if let Some(span) = info.options.rest {
+            if on_wire.next().is_some() {
+                return Err(Error::new(span, "#[rest] must be the last field"));
+            }
+        }
+    }
+
+    Ok(infos)
+}
+
+/// Pattern binding every field of `path` by reference, skipped fields are ignored.
+pub fn pattern(path: &TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+    let bindings = infos.iter().map(|info| {
+        let member = &info.member;
+        let binding = match info.options.skip {
+            Some(_) => quote!(_),
+            None => {
+                let binding = &info.binding;
+                quote!(#binding)
+            }
+        };
+        match fields {
+            Fields::Named(_) => quote!(#member: #binding),
+            _ => binding,
+        }
+    });
+
+    match fields {
+        Fields::Named(_) => quote!(#path { #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(#path ( #(#bindings),* )),
+        Fields::Unit => quote!(#path),
+    }
+}
+
+/// Expression building `path` from the bound fields.
+pub fn construct(path: &TokenStream, fields: &Fields, infos: &[FieldInfo]) -> TokenStream {
+    let bindings = infos.iter().map(|info| &info.binding);
+
+    match fields {
+        Fields::Named(_) => {
+            let members = infos.iter().map(|info| &info.member);
+            quote!(#path { #(#members: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(#path ( #(#bindings),* )),
+        Fields::Unit => quote!(#path),
+    }
+}
@@ -1,110 +1,79 @@
+//! `#[derive(Encode)]` and `#[derive(Decode)]` for packets and the types they
+//! are built from.
+//!
+//! Fields are written in declaration order with their `Encode`/`Decode` impls.
+//! Packet ids are not part of the derived impls, they come from the packet
+//! registry in `pandoranet`. A `#[packet(id, state, direction)]` container
+//! attribute is optional and checked at compile time against the `Packet` impl
+//! the registry generates for the type: the state and direction have to match,
+//! and the id has to be the packet's id in at least one protocol version.
+//!
+//! Field attributes:
+//!
+//! - `#[varint]` sends an integer as a VarInt, or a VarLong for 64 bit types.
+//! - `#[max_len = 16]` limits a string (in characters) or array (in elements).
+//! - `#[length_prefixed]` prefixes the encoded field with its size in bytes.
+//! - `#[rest]` takes up the rest of the packet, for `Vec<u8>` and `&[u8]`.
+//! - `#[optional(bool_prefix)]` marks an `Option<T>` sent after a presence
+//!   boolean, which is also how plain `Option<T>` fields are sent.
+//! - `#[skip]` leaves the field out, decoding it as `Default::default()`.
+//! - `#[nbt]` sends a `serde` type as NBT. Decoding rejects values larger than
+//!   2 MiB, or `N` bytes with `#[nbt(max_size = N)]`.
+//!
+//! Enums are sent as a VarInt discriminant followed by the variant's fields.
+//! Variant ids come from `= N` or `#[id = N]`, or count up from the previous
+//! variant starting at 0.
+
 extern crate proc_macro;
 
-use proc_macro::TokenStream;
+mod attributes;
+mod decode;
+mod encode;
+mod fields;
 
-use quote::quote;
+use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(Decode)]
+#[proc_macro_derive(
+    Decode,
+    attributes(
+        packet,
+        varint,
+        max_len,
+        length_prefixed,
+        rest,
+        optional,
+        skip,
+        nbt,
+        id
+    )
+)]
 pub fn decode_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let mut field_statements = Vec::new();
-
-    if let syn::Data::Struct(syn::DataStruct {
-        fields: syn::Fields::Named(fields),
-        ..
-    }) = input.data
-    {
-        for field in fields.named {
-            let ident = field.ident.unwrap();
-            let type_name = field.ty;
-
-            let statement = quote! {
-                #ident: match <#type_name as Decode>::decode(bytes).await {
-                    Ok(value) => Box::into_inner(value),
-                    Err(e) => {
-                        eprintln!("Decode error in field '{}': {:?}", stringify!(#ident), e);
-                        return Err(e.into());
-                    },
-                },
-            };
-            field_statements.push(statement);
-        }
-    }
 
-    let name = input.ident;
-
-    let expanded = quote! {
-        impl #name {
-            pub async fn decode<T>(bytes: &mut T) -> Result<Self, Box<dyn std::error::Error>>
-            where
-                T: AsyncRead + AsyncSeek + Unpin,
-            {
-                Ok(Self {
-                    #(#field_statements)*
-                })
-            }
-        }
-    };
-
-    TokenStream::from(expanded)
+    decode::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
 
-#[proc_macro_derive(Encode)]
+#[proc_macro_derive(
+    Encode,
+    attributes(
+        packet,
+        varint,
+        max_len,
+        length_prefixed,
+        rest,
+        optional,
+        skip,
+        nbt,
+        id
+    )
+)]
 pub fn encode_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let mut field_statements = Vec::new();
-    let mut has_packet_id = false;
-
-    if let syn::Data::Struct(syn::DataStruct {
-        fields: syn::Fields::Named(fields),
-        ..
-    }) = input.data
-    {
-        for field in fields.named {
-            let ident = field.ident.unwrap();
-            let type_name = field.ty;
-
-            if ident == "packet_id" {
-                has_packet_id = true;
-            }
-
-            let statement = quote! {
-                <#type_name as Encode>::encode(&self.#ident, &mut bytes).await?;
-            };
-            field_statements.push(statement);
-        }
-    }
-
-    if !has_packet_id {
-        return TokenStream::from(quote! {
-            compile_error!("Struct must have a packet_id field");
-        });
-    }
-
-    let name = input.ident;
-
-    let expanded = quote! {
-        impl #name {
-            pub async fn encode(&self) -> core::result::Result<Vec<u8>, std::io::Error>
-            {
-                let mut bytes = Vec::new();
-
-                #(#field_statements)*
-
-                let packet_data = bytes.clone();
-                let length = packet_data.len() as i32;
-                let var_int = packet_manager::types::varint_types::VarInt::new(length);
-
-                let mut encoded_data = Vec::new();
-                var_int.encode(&mut encoded_data).await?;
-
-                encoded_data.extend_from_slice(&packet_data);
-
-                Ok(encoded_data)
-            }
-        }
-    };
 
-    TokenStream::from(expanded)
+    encode::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
@@ -0,0 +1,5 @@
+#[test]
+fn invalid_attributes() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,363 @@
+use packet_manager::ProtocolError;
+use pandora_macros::{Decode, Encode};
+use pandora_utils::types::{decode_types::Decode, encode_types::Encode};
+
+fn round_trip<T>(value: &T, expected: &[u8])
+where
+    T: Encode + for<'a> Decode<'a> + PartialEq + std::fmt::Debug,
+{
+    let bytes = value.to_bytes().unwrap();
+    assert_eq!(bytes, expected);
+
+    let mut data = &bytes[..];
+    assert_eq!(&T::decode(&mut data).unwrap(), value);
+    assert!(data.is_empty(), "{} bytes left over", data.len());
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Plain {
+    flag: bool,
+    short: u16,
+    name: String,
+}
+
+#[test]
+fn encodes_fields_in_order() {
+    round_trip(
+        &Plain {
+            flag: true,
+            short: 0x1234,
+            name: "ab".to_string(),
+        },
+        &[0x01, 0x12, 0x34, 0x02, b'a', b'b'],
+    );
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct VarInts {
+    #[varint]
+    int: i32,
+    #[varint]
+    long: i64,
+    #[varint]
+    small: u8,
+    #[varint]
+    unsigned: u32,
+}
+
+#[test]
+fn varint_fields() {
+    round_trip(
+        &VarInts {
+            int: 300,
+            long: -1,
+            small: 200,
+            unsigned: u32::MAX,
+        },
+        &[
+            0xAC, 0x02, // 300
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // -1 as a VarLong
+            0xC8, 0x01, // 200
+            0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // u32::MAX, sent as -1
+        ],
+    );
+}
+
+#[test]
+fn varint_fields_are_range_checked() {
+    #[derive(Decode, Debug)]
+    struct Small {
+        #[varint]
+        #[allow(dead_code)]
+        value: u8,
+    }
+
+    let error = Small::decode(&mut &[0x80, 0x02][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::InvalidData {
+            field: "Small.value",
+            ..
+        }
+    ));
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Rest {
+    channel: String,
+    #[rest]
+    data: Vec<u8>,
+}
+
+#[test]
+fn rest_takes_the_remaining_bytes() {
+    round_trip(
+        &Rest {
+            channel: "a".to_string(),
+            data: vec![1, 2, 3],
+        },
+        &[0x01, b'a', 1, 2, 3],
+    );
+    round_trip(
+        &Rest {
+            channel: "a".to_string(),
+            data: vec![],
+        },
+        &[0x01, b'a'],
+    );
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct BorrowedRest<'a> {
+    id: u8,
+    #[rest]
+    data: &'a [u8],
+    #[skip]
+    cached: Option<u32>,
+}
+
+#[test]
+fn rest_can_borrow_and_be_followed_by_skipped_fields() {
+    let bytes = [0x07, 0xAA, 0xBB];
+    let mut data = &bytes[..];
+    let decoded = BorrowedRest::decode(&mut data).unwrap();
+    assert_eq!(
+        decoded,
+        BorrowedRest {
+            id: 7,
+            data: &[0xAA, 0xBB],
+            cached: None,
+        }
+    );
+    assert!(data.is_empty());
+
+    let packet = BorrowedRest {
+        cached: Some(5),
+        ..decoded
+    };
+    assert_eq!(packet.to_bytes().unwrap(), bytes);
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Skipped {
+    before: u8,
+    #[skip]
+    local: String,
+    after: u8,
+}
+
+#[test]
+fn skipped_fields_are_not_sent() {
+    let packet = Skipped {
+        before: 1,
+        local: "not sent".to_string(),
+        after: 2,
+    };
+    assert_eq!(packet.to_bytes().unwrap(), [1, 2]);
+
+    let decoded = Skipped::decode(&mut &[1, 2][..]).unwrap();
+    assert_eq!(decoded.local, "");
+    assert_eq!((decoded.before, decoded.after), (1, 2));
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Optional {
+    #[optional(bool_prefix)]
+    explicit: Option<u16>,
+    implicit: Option<u8>,
+}
+
+#[test]
+fn optional_fields_are_prefixed_with_a_bool() {
+    round_trip(
+        &Optional {
+            explicit: Some(0x0102),
+            implicit: None,
+        },
+        &[0x01, 0x01, 0x02, 0x00],
+    );
+    round_trip(
+        &Optional {
+            explicit: None,
+            implicit: Some(9),
+        },
+        &[0x00, 0x01, 0x09],
+    );
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Limited {
+    #[max_len = 3]
+    name: String,
+    #[max_len = 2]
+    ids: Vec<u8>,
+}
+
+#[test]
+fn max_len_is_checked_both_ways() {
+    round_trip(
+        &Limited {
+            name: "ééé".to_string(),
+            ids: vec![1, 2],
+        },
+        &[0x06, 0xC3, 0xA9, 0xC3, 0xA9, 0xC3, 0xA9, 0x02, 1, 2],
+    );
+
+    let too_long = Limited {
+        name: "abcd".to_string(),
+        ids: vec![],
+    };
+    assert!(too_long.to_bytes().is_err());
+
+    let error = Limited::decode(&mut &[0x04, b'a', b'b', b'c', b'd', 0x00][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::StringTooLong {
+            field: "Limited.name",
+            length: 4,
+            max: 3
+        }
+    ));
+
+    let error = Limited::decode(&mut &[0x00, 0x03, 1, 2, 3][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::StringTooLong {
+            field: "Limited.ids",
+            ..
+        }
+    ));
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct LengthPrefixed {
+    #[length_prefixed]
+    inner: Plain,
+    after: u8,
+}
+
+#[test]
+fn length_prefixed_fields_carry_their_size() {
+    round_trip(
+        &LengthPrefixed {
+            inner: Plain {
+                flag: false,
+                short: 1,
+                name: String::new(),
+            },
+            after: 9,
+        },
+        &[0x04, 0x00, 0x00, 0x01, 0x00, 0x09],
+    );
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Tuple(#[varint] i32, String, #[skip] u8);
+
+#[test]
+fn tuple_structs() {
+    round_trip(&Tuple(1, "x".to_string(), 0), &[0x01, 0x01, b'x']);
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+struct Unit;
+
+#[test]
+fn unit_structs_are_empty() {
+    round_trip(&Unit, &[]);
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+enum Action {
+    Start,
+    Move {
+        #[varint]
+        distance: i32,
+    },
+    Say(String),
+    #[id = 10]
+    Stop,
+    Land,
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+enum Hand {
+    Main = 20,
+    Off,
+}
+
+#[test]
+fn enums_are_prefixed_with_their_variant_id() {
+    round_trip(&Action::Start, &[0x00]);
+    round_trip(&Action::Move { distance: 300 }, &[0x01, 0xAC, 0x02]);
+    round_trip(&Action::Say("hi".to_string()), &[0x02, 0x02, b'h', b'i']);
+    round_trip(&Action::Stop, &[0x0A]);
+    round_trip(&Action::Land, &[0x0B]);
+    round_trip(&Hand::Main, &[0x14]);
+    round_trip(&Hand::Off, &[0x15]);
+}
+
+#[test]
+fn unknown_variants_are_rejected() {
+    let error = Action::decode(&mut &[0x05][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::InvalidData {
+            field: "Action",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn errors_name_the_field() {
+    let error = Plain::decode(&mut &[0x01, 0x12][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::Truncated {
+            field: "Plain.short",
+            ..
+        }
+    ));
+
+    let error = Action::decode(&mut &[0x01, 0x80][..]).unwrap_err();
+    assert!(matches!(
+        error,
+        ProtocolError::Truncated {
+            field: "Action::Move.distance",
+            ..
+        }
+    ));
+}
+
+#[derive(Encode, Decode, Debug, PartialEq)]
+#[packet(id = 0x19, state = Play, direction = Clientbound)]
+struct Kick<'a> {
+    reason: &'a str,
+}
+
+impl pandora_utils::types::packet_types::Packet for Kick<'_> {
+    const NAME: &'static str = "disconnect";
+    const STATE: pandora_utils::types::packet_types::PacketState =
+        pandora_utils::types::packet_types::PacketState::Play;
+    const DIRECTION: pandora_utils::types::packet_types::PacketDirection =
+        pandora_utils::types::packet_types::PacketDirection::Clientbound;
+    const IDS: &'static [(i32, i32)] = &[(754, 0x19), (756, 0x1a)];
+
+    fn id(protocol: i32) -> Option<i32> {
+        Self::IDS
+            .iter()
+            .find(|(version, _)| *version == protocol)
+            .map(|(_, id)| *id)
+    }
+}
+
+#[test]
+fn packet_attribute_matching_the_registry() {
+    let bytes = Kick { reason: "bye" }.to_bytes().unwrap();
+    assert_eq!(bytes, [0x03, b'b', b'y', b'e']);
+    assert_eq!(
+        Kick::decode(&mut &bytes[..]).unwrap(),
+        Kick { reason: "bye" }
+    );
+}
@@ -0,0 +1,10 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+struct Packet {
+    #[varint]
+    #[varint]
+    value: i32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+enum Packet {
+    First = 1,
+    #[id = 1]
+    Second,
+}
+
+fn main() {}
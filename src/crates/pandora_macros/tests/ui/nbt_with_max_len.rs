@@ -0,0 +1,10 @@
+use pandora_macros::Decode;
+
+#[derive(Decode)]
+struct Packet {
+    #[nbt]
+    #[max_len = 4]
+    value: Vec<u8>,
+}
+
+fn main() {}
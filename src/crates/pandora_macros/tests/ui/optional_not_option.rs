@@ -0,0 +1,9 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+struct Packet {
+    #[optional(bool_prefix)]
+    value: u8,
+}
+
+fn main() {}
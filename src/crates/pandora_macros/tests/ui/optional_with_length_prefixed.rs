@@ -0,0 +1,10 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+struct Packet {
+    #[optional(bool_prefix)]
+    #[length_prefixed]
+    value: Option<u8>,
+}
+
+fn main() {}
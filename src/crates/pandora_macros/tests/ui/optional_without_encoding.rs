@@ -0,0 +1,9 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+struct Packet {
+    #[optional]
+    value: Option<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use pandora_macros::Encode;
+use pandora_utils::types::packet_types::{Packet, PacketDirection, PacketState};
+
+#[derive(Encode)]
+#[packet(id = 0x1a, state = Play, direction = Clientbound)]
+struct Disconnect {
+    reason: String,
+}
+
+impl Packet for Disconnect {
+    const NAME: &'static str = "disconnect";
+    const STATE: PacketState = PacketState::Play;
+    const DIRECTION: PacketDirection = PacketDirection::Clientbound;
+    const IDS: &'static [(i32, i32)] = &[(754, 0x19)];
+
+    fn id(protocol: i32) -> Option<i32> {
+        Self::IDS.iter().find(|(p, _)| *p == protocol).map(|(_, id)| *id)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+#[packet(id = 0x01, state = Status, direction = Clientbound)]
+#[packet(id = 0x01, state = Status, direction = Clientbound)]
+struct Pong {
+    payload: i64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+#[packet(id = 0x00, state = Status)]
+struct Response {
+    json: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use pandora_macros::Decode;
+
+#[derive(Decode)]
+#[packet(id = 0x00, state = Configuration, direction = Serverbound)]
+struct Handshake {
+    #[varint]
+    protocol: i32,
+}
+
+fn main() {}
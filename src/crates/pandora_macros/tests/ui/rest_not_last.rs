@@ -0,0 +1,10 @@
+use pandora_macros::Decode;
+
+#[derive(Decode)]
+struct Packet {
+    #[rest]
+    data: Vec<u8>,
+    after: u8,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use pandora_macros::Encode;
+
+#[derive(Encode)]
+struct Packet {
+    #[skip]
+    #[rest]
+    data: Vec<u8>,
+}
+
+fn main() {}
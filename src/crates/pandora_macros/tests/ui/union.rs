@@ -0,0 +1,9 @@
+use pandora_macros::Decode;
+
+#[derive(Decode)]
+union Packet {
+    a: u32,
+    b: f32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use pandora_macros::Decode;
+
+#[derive(Decode)]
+struct Packet {
+    #[varint]
+    #[max_len = 4]
+    value: i32,
+}
+
+fn main() {}
@@ -0,0 +1,204 @@
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use crate::responses::favicon::load_favicon;
+
+lazy_static! {
+    static ref CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub max_players: u32,
+    pub motd: String,
+    /// Connections beyond this many are closed as soon as they are accepted.
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Disable Nagle's algorithm on client sockets so packets are sent right away.
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    #[serde(default)]
+    pub disconnect_on_unknown_packet: bool,
+    /// Path to a 64x64 PNG shown next to the server in the multiplayer list.
+    #[serde(default)]
+    pub favicon: Option<String>,
+    /// The favicon encoded as a data URI, filled in when the config is loaded.
+    #[serde(skip)]
+    pub favicon_data: Option<String>,
+    /// Authenticate players against the session server and encrypt the connection.
+    #[serde(default)]
+    pub online_mode: bool,
+    /// Base URL of the session server used to verify players in online mode.
+    #[serde(default = "default_session_server")]
+    pub session_server: String,
+    /// Packets of at least this many bytes are compressed, -1 disables compression.
+    #[serde(default = "default_compression_threshold")]
+    pub compression_threshold: i32,
+    /// Minecraft releases players may join with, such as `1.16.5`. Every release
+    /// players can get into the world with, 1.16.5 and 1.17.1, is accepted when
+    /// empty.
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// Game mode players join in.
+    #[serde(default)]
+    pub gamemode: GameMode,
+    #[serde(default)]
+    pub hardcore: bool,
+    /// World seed, sent hashed so clients can compute biome noise.
+    #[serde(default)]
+    pub seed: i64,
+    /// Render distance in chunks that clients are told to use.
+    #[serde(default = "default_view_distance")]
+    pub view_distance: i32,
+    #[serde(default)]
+    pub reduced_debug_info: bool,
+    #[serde(default = "default_enable_respawn_screen")]
+    pub enable_respawn_screen: bool,
+    /// Render the world as superflat, with the horizon at y 0.
+    #[serde(default)]
+    pub flat_world: bool,
+    /// Where players appear when they join.
+    #[serde(default)]
+    pub spawn: SpawnPoint,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SpawnPoint {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 64.0,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    #[default]
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl GameMode {
+    /// The id sent in Join Game.
+    pub fn id(self) -> u8 {
+        match self {
+            GameMode::Survival => 0,
+            GameMode::Creative => 1,
+            GameMode::Adventure => 2,
+            GameMode::Spectator => 3,
+        }
+    }
+}
+
+fn default_session_server() -> String {
+    "https://sessionserver.mojang.com".to_string()
+}
+
+fn default_compression_threshold() -> i32 {
+    256
+}
+
+fn default_max_connections() -> usize {
+    1024
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_view_distance() -> i32 {
+    10
+}
+
+fn default_enable_respawn_screen() -> bool {
+    true
+}
+
+impl Config {
+    pub fn load_config() -> Self {
+        let start_time = Instant::now();
+
+        let config = Self::read_config().unwrap_or_else(|err| {
+            error!("{}", err);
+            panic!("Configuration file missing or malformed");
+        });
+
+        let duration = start_time.elapsed();
+        info!("Config::load_config completed in {:?}", duration);
+        config
+    }
+
+    fn read_config() -> Result<Self, String> {
+        let config_path = Path::new("config.json");
+
+        let config_str = fs::read_to_string(config_path)
+            .map_err(|err| format!("Failed to read config file: {}", err))?;
+
+        Self::parse(&config_str)
+    }
+
+    /// Parses a configuration in the format of `config.json` and loads its favicon.
+    pub fn parse(config_str: &str) -> Result<Self, String> {
+        let mut config: Config = serde_json::from_str(config_str)
+            .map_err(|err| format!("Failed to parse config file: {}", err))?;
+
+        if let Some(path) = &config.favicon {
+            match load_favicon(path) {
+                Ok(data) => config.favicon_data = Some(data),
+                Err(err) => warn!("{}, the server list will show the default icon", err),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the active server configuration, loading `config.json` on first use.
+    pub fn get() -> Arc<Config> {
+        if let Some(config) = CONFIG.read().unwrap().as_ref() {
+            return config.clone();
+        }
+
+        CONFIG
+            .write()
+            .unwrap()
+            .get_or_insert_with(|| Arc::new(Config::load_config()))
+            .clone()
+    }
+
+    /// Replaces the active configuration without reading `config.json`.
+    pub fn set(config: Config) {
+        *CONFIG.write().unwrap() = Some(Arc::new(config));
+    }
+
+    /// Re-reads `config.json` and replaces the active configuration. The previous
+    /// configuration stays in place if the file cannot be loaded.
+    pub fn reload() -> Result<(), String> {
+        Self::set(Self::read_config()?);
+        info!("Config reloaded");
+        Ok(())
+    }
+}
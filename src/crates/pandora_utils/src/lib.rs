@@ -1,4 +1,5 @@
-pub mod position;
+pub mod config;
 pub mod read_file;
 pub mod responses;
+pub mod tick_stats;
 pub mod types;
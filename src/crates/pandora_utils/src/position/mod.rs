@@ -1 +0,0 @@
-pub mod send_player_position;
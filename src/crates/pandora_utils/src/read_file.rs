@@ -1,17 +1,13 @@
-use std::{
-    fs::File,
-    io::{Cursor, Read},
-    path::Path,
-};
+use std::{fs::File, io::BufReader, path::Path};
 
 use nbt::Blob;
 
+/// Reads an uncompressed NBT file.
 pub fn read_file(path: &str) -> Result<Blob, Box<dyn std::error::Error>> {
-    let path = Path::new(path);
-    let mut file = File::open(path).unwrap();
-    let mut data = Vec::new();
-    file.read_to_end(&mut data).unwrap();
-    let blob = Blob::from_reader(&mut Cursor::new(data)).unwrap();
+    let file =
+        File::open(Path::new(path)).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    let blob = Blob::from_reader(&mut BufReader::new(file))
+        .map_err(|err| format!("Failed to read {}: {}", path, err))?;
 
     Ok(blob)
 }
@@ -0,0 +1,119 @@
+use std::fs;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const FAVICON_SIZE: u32 = 64;
+
+/// Reads the PNG at `path`, checks it is a 64x64 image and returns it as a
+/// `data:image/png;base64,...` URI ready to be placed in the status response.
+pub fn load_favicon(path: &str) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read favicon {}: {}", path, e))?;
+
+    // The IHDR chunk always directly follows the signature and holds the dimensions.
+    if data.len() < 24 || data[..8] != PNG_SIGNATURE || &data[12..16] != b"IHDR" {
+        return Err(format!("Favicon {} is not a PNG image", path));
+    }
+
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    if width != FAVICON_SIZE || height != FAVICON_SIZE {
+        return Err(format!(
+            "Favicon {} must be {}x{} pixels, got {}x{}",
+            path, FAVICON_SIZE, FAVICON_SIZE, width, height
+        ));
+    }
+
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(&data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{config::Config, responses::send_status_response::build_status_response};
+
+    /// The signature and IHDR chunk of a PNG, which is all `load_favicon` reads.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    fn write_temp(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("pandora-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn encodes_a_64x64_png_as_a_data_uri() {
+        let data = png(64, 64);
+        let path = write_temp("valid.png", &data);
+
+        let favicon = load_favicon(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            favicon,
+            format!("data:image/png;base64,{}", STANDARD.encode(&data))
+        );
+        assert!(favicon.starts_with("data:image/png;base64,iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut gif = png(64, 64);
+        gif[..6].copy_from_slice(b"GIF89a");
+        let cases = [
+            ("signature.png", gif),
+            ("size.png", png(128, 64)),
+            ("height.png", png(64, 32)),
+            ("short.png", PNG_SIGNATURE.to_vec()),
+        ];
+
+        for (name, data) in cases {
+            let path = write_temp(name, &data);
+            assert!(load_favicon(path.to_str().unwrap()).is_err(), "{}", name);
+        }
+
+        let missing = std::env::temp_dir().join("pandora-missing-favicon.png");
+        let error = load_favicon(missing.to_str().unwrap()).unwrap_err();
+        assert!(error.starts_with("Failed to read favicon"), "{}", error);
+    }
+
+    #[test]
+    fn status_response_leaves_out_a_favicon_that_failed_to_load() {
+        let config = |favicon: &str| {
+            Config::parse(&format!(
+                r#"{{
+                    "host": "127.0.0.1",
+                    "port": 25565,
+                    "max_players": 20,
+                    "motd": "A Pandora server",
+                    "favicon": {}
+                }}"#,
+                serde_json::to_string(favicon).unwrap()
+            ))
+            .unwrap()
+        };
+
+        let missing = std::env::temp_dir().join("pandora-missing-favicon.png");
+        let bad = config(missing.to_str().unwrap());
+        assert_eq!(bad.favicon_data, None);
+        Config::set(bad);
+        let response = build_status_response("1.16.5", 754, 0, &[]);
+        assert!(response.get("favicon").is_none());
+        assert_eq!(response["description"]["text"], "A Pandora server");
+
+        let path = write_temp("status.png", &png(64, 64));
+        let good = config(path.to_str().unwrap());
+        let favicon = good.favicon_data.clone().unwrap();
+        Config::set(good);
+        let response = build_status_response("1.16.5", 754, 0, &[]);
+        assert_eq!(response["favicon"], favicon);
+    }
+}
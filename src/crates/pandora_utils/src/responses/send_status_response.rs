@@ -1,32 +1,50 @@
-use std::sync::Arc;
+use serde::Serialize;
+use serde_json::{json, Value};
 
-use bytes::BytesMut;
-use log::error;
-use packet_manager::PacketManager;
-use serde_json::json;
-use tokio::{io::AsyncWriteExt, net::TcpStream, sync::Mutex};
+use crate::config::Config;
 
-pub async fn send_status_response(socket: &Arc<Mutex<TcpStream>>, max_players: u32) {
-    let response_data = json!({
+/// Maximum number of players listed in the hover sample of the server list.
+pub const STATUS_SAMPLE_SIZE: usize = 12;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+    /// Round trip time in milliseconds. Not part of the vanilla format, clients
+    /// ignore it but other tools querying the server can read it.
+    pub latency: u32,
+}
+
+/// Builds the Server List Ping JSON from the active config and the given player counts.
+///
+/// Clients show the server as compatible when `protocol` matches their own, and
+/// `version_name` otherwise.
+pub fn build_status_response(
+    version_name: &str,
+    protocol: i32,
+    online: u32,
+    sample: &[PlayerSample],
+) -> Value {
+    let config = Config::get();
+
+    let mut response = json!({
         "version": {
-            "name": "1.16.5",
-            "protocol": 754,
+            "name": version_name,
+            "protocol": protocol,
         },
         "players": {
-            "max": max_players,
-            "online": 0,
+            "max": config.max_players,
+            "online": online,
+            "sample": sample,
+        },
+        "description": {
+            "text": config.motd,
         },
     });
 
-    let mut response = PacketManager::new(BytesMut::new(), 0);
-    response.write_string(&serde_json::to_string(&response_data).unwrap());
-
-    if let Err(err) = socket
-        .lock()
-        .await
-        .write_all(&response.build_packet(0x00))
-        .await
-    {
-        error!("Failed to send status response: {}", err);
+    if let Some(favicon) = &config.favicon_data {
+        response["favicon"] = Value::String(favicon.clone());
     }
+
+    response
 }
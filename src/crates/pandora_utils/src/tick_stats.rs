@@ -0,0 +1,178 @@
+//! Tick timing of the game loop, shared so commands and metrics can report it.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use lazy_static::lazy_static;
+use tokio::time::Instant;
+
+pub const TICKS_PER_SECOND: u32 = 20;
+pub const TICK_DURATION: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
+/// Number of recent ticks the averages cover, five seconds at full speed.
+pub const SAMPLE_SIZE: usize = 100;
+
+lazy_static! {
+    pub static ref TICK_STATS: TickStats = TickStats::new();
+}
+
+/// Start and duration of the last `SAMPLE_SIZE` ticks.
+pub struct TickStats {
+    samples: Mutex<Samples>,
+}
+
+#[derive(Default)]
+struct Samples {
+    ticks: u64,
+    recent: VecDeque<(Instant, Duration)>,
+}
+
+/// Tick statistics over the last `SAMPLE_SIZE` ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TickReport {
+    /// Ticks run since the server started.
+    pub ticks: u64,
+    /// Average milliseconds per tick.
+    pub mspt: f64,
+    /// Longest tick in milliseconds.
+    pub max_mspt: f64,
+    /// Ticks per second, at most `TICKS_PER_SECOND`.
+    pub tps: f64,
+}
+
+impl TickStats {
+    pub fn new() -> Self {
+        Self {
+            samples: Mutex::new(Samples::default()),
+        }
+    }
+
+    /// Records a tick that started at `start` and took `duration`.
+    pub fn record(&self, start: Instant, duration: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.ticks += 1;
+        if samples.recent.len() == SAMPLE_SIZE {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back((start, duration));
+    }
+
+    pub fn report(&self) -> TickReport {
+        let samples = self.samples.lock().unwrap();
+        let recent = &samples.recent;
+
+        let total: Duration = recent.iter().map(|(_, duration)| *duration).sum();
+        let max = recent
+            .iter()
+            .map(|(_, duration)| *duration)
+            .max()
+            .unwrap_or_default();
+        let mspt = match recent.len() {
+            0 => 0.0,
+            len => total.as_secs_f64() * 1000.0 / len as f64,
+        };
+
+        // Ticks started per second between the first and last sample. Ticks run
+        // back to back while catching up, which is not counted as running fast.
+        let tps = match (recent.front(), recent.back()) {
+            (Some((first, _)), Some((last, _))) if last > first => {
+                (recent.len() - 1) as f64 / (*last - *first).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+
+        TickReport {
+            ticks: samples.ticks,
+            mspt,
+            max_mspt: max.as_secs_f64() * 1000.0,
+            tps: tps.min(TICKS_PER_SECOND as f64),
+        }
+    }
+}
+
+impl Default for TickStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn reports_nothing_before_the_first_tick() {
+        let report = TickStats::new().report();
+        assert_eq!(
+            report,
+            TickReport {
+                ticks: 0,
+                mspt: 0.0,
+                max_mspt: 0.0,
+                tps: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn averages_tick_durations() {
+        let stats = TickStats::new();
+        let start = Instant::now();
+        stats.record(start, ms(10));
+        stats.record(start + ms(50), ms(20));
+        stats.record(start + ms(100), ms(30));
+
+        let report = stats.report();
+        assert_eq!(report.ticks, 3);
+        assert_close(report.mspt, 20.0);
+        assert_close(report.max_mspt, 30.0);
+        assert_close(report.tps, 20.0);
+    }
+
+    #[test]
+    fn measures_tps_from_tick_starts() {
+        let stats = TickStats::new();
+        let start = Instant::now();
+        for tick in 0..11 {
+            stats.record(start + ms(100) * tick, ms(90));
+        }
+        assert_close(stats.report().tps, 10.0);
+
+        // Ticks run back to back while catching up do not count as more than
+        // full speed.
+        let stats = TickStats::new();
+        for tick in 0..11 {
+            stats.record(start + ms(1) * tick, ms(1));
+        }
+        assert_close(stats.report().tps, TICKS_PER_SECOND as f64);
+    }
+
+    #[test]
+    fn covers_only_the_recent_ticks() {
+        let stats = TickStats::new();
+        let start = Instant::now();
+        for tick in 0..10 {
+            stats.record(start + ms(50) * tick, ms(500));
+        }
+        for tick in 10..10 + SAMPLE_SIZE as u32 {
+            stats.record(start + ms(50) * tick, ms(5));
+        }
+
+        let report = stats.report();
+        assert_eq!(report.ticks, 10 + SAMPLE_SIZE as u64);
+        assert_close(report.mspt, 5.0);
+        assert_close(report.max_mspt, 5.0);
+        assert_close(report.tps, 20.0);
+    }
+}
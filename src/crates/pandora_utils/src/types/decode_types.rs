@@ -1,113 +1,176 @@
-use std::error::Error;
+use bytes::Buf;
+use packet_manager::types::{varint_types::VarInt, varlong_types::VarLong};
+pub use packet_manager::{packet_manager::MAX_STRING_LENGTH, ProtocolError};
+use uuid::Uuid;
 
-use packet_manager::{types::varint_types::VarInt, PacketManager};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek};
+/// Reads a value from a fully buffered packet, advancing `bytes` past it.
+///
+/// The trait is tied to the lifetime of the packet data so that values such as
+/// `&str` and `&[u8]` can borrow from it instead of copying.
+pub trait Decode<'a>: Sized {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError>;
+}
 
-pub trait Decode {
-    #[allow(async_fn_in_trait)]
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin;
+fn ensure_remaining(bytes: &[u8], needed: usize, field: &'static str) -> Result<(), ProtocolError> {
+    if bytes.len() < needed {
+        return Err(ProtocolError::Truncated {
+            field,
+            needed,
+            remaining: bytes.len(),
+        });
+    }
+    Ok(())
 }
 
-impl Decode for bool {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let mut buf = [0u8; 1];
-        bytes.read_exact(&mut buf).await.map_err(|_| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to read bool",
-            ))
-        })?;
-        Ok(Box::from(buf[0] != 0))
+/// Reads a VarInt length prefix and checks that the data it describes is present.
+fn decode_length(bytes: &mut &[u8], field: &'static str) -> Result<usize, ProtocolError> {
+    let length = VarInt::decode(bytes)
+        .map_err(|e| e.in_field(field))?
+        .to_i32();
+    let length = usize::try_from(length).map_err(|_| ProtocolError::InvalidData {
+        field,
+        reason: format!("negative length {}", length),
+    })?;
+
+    ensure_remaining(bytes, length, field)?;
+    Ok(length)
+}
+
+fn split_off<'a>(bytes: &mut &'a [u8], length: usize) -> &'a [u8] {
+    let (data, rest) = bytes.split_at(length);
+    *bytes = rest;
+    data
+}
+
+macro_rules! decode_number {
+    ($($ty:ty => $get:ident),* $(,)?) => {
+        $(
+            impl<'a> Decode<'a> for $ty {
+                fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+                    ensure_remaining(bytes, std::mem::size_of::<$ty>(), stringify!($ty))?;
+                    Ok(bytes.$get())
+                }
+            }
+        )*
+    };
+}
+
+decode_number!(
+    u8 => get_u8,
+    i8 => get_i8,
+    u16 => get_u16,
+    i16 => get_i16,
+    u32 => get_u32,
+    i32 => get_i32,
+    u64 => get_u64,
+    i64 => get_i64,
+    f32 => get_f32,
+    f64 => get_f64,
+);
+
+impl<'a> Decode<'a> for bool {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        ensure_remaining(bytes, 1, "bool")?;
+        Ok(bytes.get_u8() != 0)
     }
 }
 
-impl Decode for u8 {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let mut buf = [0u8; 1];
-        bytes.read_exact(&mut buf).await.map_err(|_| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to read u8",
-            ))
-        })?;
-        Ok(Box::from(buf[0]))
+impl<'a> Decode<'a> for VarInt {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let varint = VarInt::from_bytes(bytes)?;
+        bytes.advance(varint.len);
+        Ok(varint)
     }
 }
 
-impl Decode for u16 {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let mut buf = [0u8; 2];
-        bytes.read_exact(&mut buf).await.map_err(|_| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to read u16",
-            ))
-        })?;
-        Ok(Box::from(u16::from_be_bytes(buf)))
+impl<'a> Decode<'a> for VarLong {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let varlong = VarLong::from_bytes(bytes)?;
+        bytes.advance(varlong.len);
+        Ok(varlong)
     }
 }
 
-impl Decode for u32 {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let mut buf = [0u8; 4];
-        bytes.read_exact(&mut buf).await.map_err(|_| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to read u32",
-            ))
+impl<'a> Decode<'a> for &'a str {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        // A character takes at most 4 bytes, which rejects oversized strings
+        // before they are validated.
+        let length = decode_length(bytes, "String")?;
+        if length > MAX_STRING_LENGTH * 4 {
+            return Err(ProtocolError::StringTooLong {
+                field: "String",
+                length,
+                max: MAX_STRING_LENGTH,
+            });
+        }
+
+        let string = std::str::from_utf8(split_off(bytes, length)).map_err(|source| {
+            ProtocolError::InvalidUtf8 {
+                field: "String",
+                source,
+            }
         })?;
-        Ok(Box::from(u32::from_be_bytes(buf)))
+
+        let chars = string.chars().count();
+        if chars > MAX_STRING_LENGTH {
+            return Err(ProtocolError::StringTooLong {
+                field: "String",
+                length: chars,
+                max: MAX_STRING_LENGTH,
+            });
+        }
+
+        Ok(string)
     }
 }
 
-impl Decode for i32 {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let mut buf = [0u8; 4];
-        bytes.read_exact(&mut buf).await.map_err(|_| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to read i32",
-            ))
-        })?;
-        Ok(Box::from(i32::from_be_bytes(buf)))
+impl<'a> Decode<'a> for String {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        <&str>::decode(bytes).map(str::to_owned)
+    }
+}
+
+impl<'a> Decode<'a> for &'a [u8] {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let length = decode_length(bytes, "byte array")?;
+        Ok(split_off(bytes, length))
     }
 }
 
-impl Decode for String {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        let remaining_bytes: VarInt = PacketManager::read_var_int(bytes).await?;
+/// Arrays are prefixed with their element count as a VarInt.
+impl<'a, T: Decode<'a>> Decode<'a> for Vec<T> {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let count = VarInt::decode(bytes)
+            .map_err(|e| e.in_field("Array"))?
+            .to_i32();
+        let count = usize::try_from(count).map_err(|_| ProtocolError::InvalidData {
+            field: "Array",
+            reason: format!("negative length {}", count),
+        })?;
+
+        // Every element takes at least one byte, so the remaining data bounds
+        // the allocation for a bogus count.
+        let mut items = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            items.push(T::decode(bytes)?);
+        }
+        Ok(items)
+    }
+}
 
-        let mut string = vec![0u8; remaining_bytes.to_i32() as usize];
-        bytes.read_exact(&mut string).await?;
-        Ok(Box::from(String::from_utf8(string)?))
+/// Optional values are prefixed with a boolean telling whether they are present.
+impl<'a, T: Decode<'a>> Decode<'a> for Option<T> {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        match bool::decode(bytes)? {
+            true => T::decode(bytes).map(Some),
+            false => Ok(None),
+        }
     }
 }
 
-impl Decode for VarInt {
-    async fn decode<T>(bytes: &mut T) -> Result<Box<Self>, Box<dyn Error>>
-    where
-        T: AsyncRead + AsyncSeek + Unpin,
-    {
-        Ok(Box::from(PacketManager::read_var_int(bytes).await?))
+impl<'a> Decode<'a> for Uuid {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        ensure_remaining(bytes, 16, "Uuid")?;
+        Ok(Uuid::from_u128(bytes.get_u128()))
     }
 }
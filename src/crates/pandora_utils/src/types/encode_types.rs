@@ -1,123 +1,134 @@
-use packet_manager::types::varint_types::VarInt;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use std::io::{Error, ErrorKind};
 
+pub use bytes::BufMut;
+use packet_manager::types::{varint_types::VarInt, varlong_types::VarLong};
+use uuid::Uuid;
+
+/// Writes a value in its protocol representation.
 pub trait Encode {
-    #[allow(async_fn_in_trait)]
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin;
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error>;
+
+    /// Encodes the value into a new buffer.
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes)?;
+        Ok(bytes)
+    }
 }
 
+macro_rules! encode_number {
+    ($($ty:ty => $put:ident),* $(,)?) => {
+        $(
+            impl Encode for $ty {
+                fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+                    bytes.$put(*self);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+encode_number!(
+    u8 => put_u8,
+    i8 => put_i8,
+    u16 => put_u16,
+    i16 => put_i16,
+    u32 => put_u32,
+    i32 => put_i32,
+    u64 => put_u64,
+    i64 => put_i64,
+    f32 => put_f32,
+    f64 => put_f64,
+);
+
 impl Encode for bool {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        let buf = if *self { [1u8] } else { [0u8] };
-        bytes.write_all(&buf).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write bool")
-        })
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        bytes.put_u8(*self as u8);
+        Ok(())
     }
 }
 
-impl Encode for u8 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes
-            .write_all(&[*self])
-            .await
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write u8"))
+impl Encode for VarInt {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        let (buf, len) = self.to_bytes();
+        bytes.put_slice(&buf[..len]);
+        Ok(())
     }
 }
 
-impl Encode for u16 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write u16")
-        })
+impl Encode for VarLong {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        let (buf, len) = self.to_bytes();
+        bytes.put_slice(&buf[..len]);
+        Ok(())
     }
 }
 
-impl Encode for u32 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write u32")
-        })
+/// Writes the VarInt length prefix of a string or array.
+fn encode_length<B: BufMut>(len: usize, type_name: &str, bytes: &mut B) -> Result<(), Error> {
+    let len = i32::try_from(len).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} of length {} is too long", type_name, len),
+        )
+    })?;
+    VarInt::new(len).encode(bytes)
+}
+
+/// Arrays are prefixed with their element count as a VarInt.
+impl<T: Encode> Encode for [T] {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        encode_length(self.len(), "Array", bytes)?;
+        for item in self {
+            item.encode(bytes)?;
+        }
+        Ok(())
     }
 }
 
-impl Encode for i32 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write i32")
-        })
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        self.as_slice().encode(bytes)
     }
 }
 
-impl Encode for i64 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write i64")
-        })
+impl Encode for str {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        encode_length(self.len(), "String", bytes)?;
+        bytes.put_slice(self.as_bytes());
+        Ok(())
     }
 }
 
-impl Encode for f32 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write f32")
-        })
+impl Encode for String {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        self.as_str().encode(bytes)
     }
 }
 
-impl Encode for f64 {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write f64")
-        })
+/// Optional values are prefixed with a boolean telling whether they are present.
+impl<T: Encode> Encode for Option<T> {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        match self {
+            Some(value) => {
+                true.encode(bytes)?;
+                value.encode(bytes)
+            }
+            None => false.encode(bytes),
+        }
     }
 }
 
-impl Encode for String {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        let len = VarInt::new(self.len() as i32);
-        len.encode(bytes).await?;
-        bytes.write_all(&self.as_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write String")
-        })
+impl Encode for Uuid {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        bytes.put_u128(self.as_u128());
+        Ok(())
     }
 }
 
-impl Encode for VarInt {
-    async fn encode<T>(&self, bytes: &mut T) -> Result<(), std::io::Error>
-    where
-        T: AsyncWrite + Unpin,
-    {
-        bytes.write_all(&self.to_be_bytes()).await.map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to write VarInt")
-        })
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        (**self).encode(bytes)
     }
 }
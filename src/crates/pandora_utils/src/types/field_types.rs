@@ -0,0 +1,198 @@
+//! Support for the field attributes of `#[derive(Encode, Decode)]`.
+
+use std::io::{Error, ErrorKind};
+
+use bytes::BufMut;
+use packet_manager::{
+    types::{varint_types::VarInt, varlong_types::VarLong},
+    ProtocolError,
+};
+
+use crate::types::{decode_types::Decode, encode_types::Encode};
+
+/// Integers that can be sent as a VarInt or VarLong with `#[varint]`.
+///
+/// 64 bit integers use a VarLong. Unsigned integers are sent with the bits of
+/// their signed counterpart, narrower integers are range checked.
+pub trait VarIntField: Sized {
+    fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error>;
+    fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError>;
+}
+
+impl VarIntField for i32 {
+    fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        VarInt::new(*self).encode(bytes)
+    }
+
+    fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError> {
+        Ok(VarInt::decode(bytes)?.to_i32())
+    }
+}
+
+impl VarIntField for u32 {
+    fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        VarInt::new(*self as i32).encode(bytes)
+    }
+
+    fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError> {
+        Ok(VarInt::decode(bytes)?.to_i32() as u32)
+    }
+}
+
+impl VarIntField for i64 {
+    fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        VarLong::new(*self).encode(bytes)
+    }
+
+    fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError> {
+        Ok(VarLong::decode(bytes)?.to_i64())
+    }
+}
+
+impl VarIntField for u64 {
+    fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        VarLong::new(*self as i64).encode(bytes)
+    }
+
+    fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError> {
+        Ok(VarLong::decode(bytes)?.to_i64() as u64)
+    }
+}
+
+macro_rules! var_int_field {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl VarIntField for $ty {
+                fn encode_var<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+                    let value = i32::try_from(*self).map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("{} does not fit in a VarInt", self),
+                        )
+                    })?;
+                    VarInt::new(value).encode(bytes)
+                }
+
+                fn decode_var(bytes: &mut &[u8]) -> Result<Self, ProtocolError> {
+                    let value = VarInt::decode(bytes)?.to_i32();
+                    <$ty>::try_from(value).map_err(|_| ProtocolError::InvalidData {
+                        field: "VarInt",
+                        reason: format!("{} is out of range for {}", value, stringify!($ty)),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+var_int_field!(i8, u8, i16, u16, usize, isize);
+
+/// Values whose length can be limited with `#[max_len = N]`. Strings are
+/// measured in characters, arrays in elements.
+pub trait BoundedLength {
+    fn bounded_len(&self) -> usize;
+}
+
+impl BoundedLength for str {
+    fn bounded_len(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl BoundedLength for String {
+    fn bounded_len(&self) -> usize {
+        self.as_str().bounded_len()
+    }
+}
+
+impl<T> BoundedLength for [T] {
+    fn bounded_len(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> BoundedLength for Vec<T> {
+    fn bounded_len(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: BoundedLength + ?Sized> BoundedLength for &T {
+    fn bounded_len(&self) -> usize {
+        (**self).bounded_len()
+    }
+}
+
+pub fn check_max_len<T: BoundedLength + ?Sized>(
+    value: &T,
+    max: usize,
+    field: &'static str,
+) -> Result<(), ProtocolError> {
+    let length = value.bounded_len();
+    if length > max {
+        return Err(ProtocolError::StringTooLong { field, length, max });
+    }
+    Ok(())
+}
+
+/// Writes `value` prefixed with its encoded size in bytes, for
+/// `#[length_prefixed]`.
+pub fn encode_length_prefixed<T, B>(value: &T, bytes: &mut B) -> Result<(), Error>
+where
+    T: Encode + ?Sized,
+    B: BufMut,
+{
+    let data = value.to_bytes()?;
+    let len = i32::try_from(data.len()).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Field of {} bytes is too long", data.len()),
+        )
+    })?;
+
+    VarInt::new(len).encode(bytes)?;
+    bytes.put_slice(&data);
+    Ok(())
+}
+
+/// Reads a value written by [`encode_length_prefixed`]. The value must use all
+/// of the bytes it was given.
+pub fn decode_length_prefixed<'a, T: Decode<'a>>(bytes: &mut &'a [u8]) -> Result<T, ProtocolError> {
+    let mut data = <&[u8]>::decode(bytes)?;
+    let value = T::decode(&mut data)?;
+
+    if !data.is_empty() {
+        return Err(ProtocolError::InvalidData {
+            field: "length prefixed value",
+            reason: format!("{} trailing bytes", data.len()),
+        });
+    }
+
+    Ok(value)
+}
+
+/// Byte fields that take up the rest of the packet with `#[rest]`.
+pub trait RestField<'a>: Sized {
+    fn encode_rest<B: BufMut>(&self, bytes: &mut B);
+    fn decode_rest(bytes: &mut &'a [u8]) -> Self;
+}
+
+impl<'a> RestField<'a> for &'a [u8] {
+    fn encode_rest<B: BufMut>(&self, bytes: &mut B) {
+        bytes.put_slice(self);
+    }
+
+    fn decode_rest(bytes: &mut &'a [u8]) -> Self {
+        std::mem::take(bytes)
+    }
+}
+
+impl<'a> RestField<'a> for Vec<u8> {
+    fn encode_rest<B: BufMut>(&self, bytes: &mut B) {
+        bytes.put_slice(self);
+    }
+
+    fn decode_rest(bytes: &mut &'a [u8]) -> Self {
+        std::mem::take(bytes).to_vec()
+    }
+}
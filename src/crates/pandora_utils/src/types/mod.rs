@@ -1,2 +1,5 @@
 pub mod decode_types;
 pub mod encode_types;
+pub mod field_types;
+pub mod nbt_types;
+pub mod packet_types;
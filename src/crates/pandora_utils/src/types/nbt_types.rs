@@ -0,0 +1,266 @@
+//! NBT values inside packets.
+//!
+//! Values are sent as a root tag with an empty name. Decoding checks the data
+//! against [`NbtLimits`] before parsing it, `nbt::Blob`, `nbt::Value` and
+//! `RawNbt` use the defaults and `#[nbt(max_size = N)]` fields their own size.
+//!
+//! `#[nbt]` fields go through `serde`, which cannot keep every tag type apart,
+//! so NBT of arbitrary shape is best kept in a `Blob` or `Value` field.
+
+use std::io::{Error, ErrorKind};
+
+use bytes::{Buf, BufMut, Bytes};
+use nbt::{Blob, Value};
+pub use packet_manager::types::nbt_types::{NbtLimits, MAX_NBT_DEPTH, MAX_NBT_SIZE};
+use packet_manager::{types::nbt_types::nbt_size, ProtocolError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::types::{decode_types::Decode, encode_types::Encode};
+
+fn invalid_nbt(error: nbt::Error) -> ProtocolError {
+    ProtocolError::InvalidData {
+        field: "NBT",
+        reason: error.to_string(),
+    }
+}
+
+fn nbt_write_error(error: nbt::Error) -> Error {
+    Error::new(ErrorKind::InvalidInput, error.to_string())
+}
+
+/// Splits the root tag off the front of `bytes` once it passed `limits`.
+fn split_nbt<'a>(bytes: &mut &'a [u8], limits: NbtLimits) -> Result<&'a [u8], ProtocolError> {
+    let size = nbt_size(bytes, limits)?;
+    let (data, rest) = bytes.split_at(size);
+    *bytes = rest;
+    Ok(data)
+}
+
+impl Encode for Blob {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        self.to_writer(&mut bytes.writer()).map_err(nbt_write_error)
+    }
+}
+
+impl<'a> Decode<'a> for Blob {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let mut data = split_nbt(bytes, NbtLimits::default())?;
+        Blob::from_reader(&mut data).map_err(invalid_nbt)
+    }
+}
+
+impl Encode for Value {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        bytes.put_u8(self.id());
+        bytes.put_u16(0);
+        self.to_writer(&mut bytes.writer()).map_err(nbt_write_error)
+    }
+}
+
+impl<'a> Decode<'a> for Value {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let mut data = split_nbt(bytes, NbtLimits::default())?;
+
+        let id = data.get_u8();
+        if id == 0 {
+            return Err(ProtocolError::InvalidData {
+                field: "NBT",
+                reason: "no value".to_string(),
+            });
+        }
+        let name_length = data.get_u16() as usize;
+        data.advance(name_length);
+
+        Value::from_reader(id, &mut data).map_err(invalid_nbt)
+    }
+}
+
+/// Writes a `serde` value as NBT, for `#[nbt]` fields.
+pub fn encode_nbt<T, B>(value: &T, bytes: &mut B) -> Result<(), Error>
+where
+    T: Serialize + ?Sized,
+    B: BufMut,
+{
+    nbt::to_writer(&mut bytes.writer(), value, None).map_err(nbt_write_error)
+}
+
+/// Reads a `serde` value from NBT within `limits`, for `#[nbt]` fields.
+pub fn decode_nbt<T: DeserializeOwned>(
+    bytes: &mut &[u8],
+    limits: NbtLimits,
+) -> Result<T, ProtocolError> {
+    let data = split_nbt(bytes, limits)?;
+    nbt::from_reader(data).map_err(invalid_nbt)
+}
+
+/// An NBT compound kept in its encoded form and written to packets as it is.
+/// Large values that never change, like the dimension codec, are encoded once
+/// instead of for every packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawNbt(Bytes);
+
+impl RawNbt {
+    pub fn from_blob(blob: &Blob) -> Result<Self, Error> {
+        Ok(RawNbt(Encode::to_bytes(blob)?.into()))
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        Ok(RawNbt(Encode::to_bytes(value)?.into()))
+    }
+
+    pub fn to_blob(&self) -> Result<Blob, ProtocolError> {
+        Blob::decode(&mut self.as_bytes())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Encode for RawNbt {
+    fn encode<B: BufMut>(&self, bytes: &mut B) -> Result<(), Error> {
+        bytes.put_slice(&self.0);
+        Ok(())
+    }
+}
+
+/// Reads one root tag, which is only checked, not parsed.
+impl<'a> Decode<'a> for RawNbt {
+    fn decode(bytes: &mut &'a [u8]) -> Result<Self, ProtocolError> {
+        let data = split_nbt(bytes, NbtLimits::default())?;
+        Ok(RawNbt(Bytes::copy_from_slice(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packet_manager::types::varint_types::VarInt;
+
+    use super::*;
+
+    const DIMENSION_CODEC: &[u8] = include_bytes!("../../../../../assets/dimension_codec.nbt");
+    /// A framed 1.16.5 Chunk Data packet, with the heightmaps as NBT.
+    const CHUNK_DATA: &[u8] = include_bytes!("../../../../../assets/chunk.nbt");
+    /// A gzipped structure file.
+    const MANSION: &[u8] = include_bytes!("../../../../../assets/mansion_redburg.nbt");
+
+    /// The heightmaps of `CHUNK_DATA` and everything after them.
+    fn chunk_heightmaps() -> &'static [u8] {
+        let mut data = CHUNK_DATA;
+        for _ in 0..2 {
+            let varint = VarInt::from_bytes(data).unwrap();
+            data = &data[varint.len..];
+        }
+        // Chunk x and z, full chunk.
+        data = &data[9..];
+        let bitmask = VarInt::from_bytes(data).unwrap();
+        &data[bitmask.len..]
+    }
+
+    fn mansion() -> Blob {
+        Blob::from_gzip_reader(&mut &MANSION[..]).unwrap()
+    }
+
+    fn assert_blob_round_trip(data: &[u8]) -> Blob {
+        let mut rest = data;
+        let blob = Blob::decode(&mut rest).unwrap();
+        let size = data.len() - rest.len();
+        assert_eq!(size, nbt_size(data, NbtLimits::default()).unwrap());
+
+        let encoded = blob.to_bytes().unwrap();
+        assert_eq!(encoded.len(), size);
+        assert_eq!(Blob::decode(&mut &encoded[..]).unwrap(), blob);
+        blob
+    }
+
+    fn assert_value_round_trip(data: &[u8]) {
+        let mut rest = data;
+        let value = Value::decode(&mut rest).unwrap();
+        assert!(matches!(value, Value::Compound(_)));
+        assert_eq!(
+            data.len() - rest.len(),
+            nbt_size(data, NbtLimits::default()).unwrap()
+        );
+
+        let encoded = value.to_bytes().unwrap();
+        assert_eq!(Value::decode(&mut &encoded[..]).unwrap(), value);
+    }
+
+    fn assert_raw_round_trip(data: &[u8]) {
+        let mut rest = data;
+        let raw = RawNbt::decode(&mut rest).unwrap();
+        let size = data.len() - rest.len();
+        assert_eq!(raw.as_bytes(), &data[..size]);
+        assert_eq!(raw.to_bytes().unwrap(), &data[..size]);
+        assert_eq!(
+            raw.to_blob().unwrap(),
+            Blob::decode(&mut &data[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_the_dimension_codec() {
+        let blob = assert_blob_round_trip(DIMENSION_CODEC);
+        assert!(matches!(
+            blob["minecraft:dimension_type"],
+            Value::Compound(_)
+        ));
+        assert!(matches!(
+            blob["minecraft:worldgen/biome"],
+            Value::Compound(_)
+        ));
+
+        assert_value_round_trip(DIMENSION_CODEC);
+        assert_raw_round_trip(DIMENSION_CODEC);
+        assert_eq!(RawNbt::from_blob(&blob).unwrap().to_blob().unwrap(), blob);
+    }
+
+    #[test]
+    fn round_trips_chunk_heightmaps() {
+        let data = chunk_heightmaps();
+        let blob = assert_blob_round_trip(data);
+        assert!(matches!(blob["MOTION_BLOCKING"], Value::LongArray(_)));
+
+        assert_value_round_trip(data);
+        assert_raw_round_trip(data);
+
+        // The chunk sections follow the heightmaps.
+        let mut rest = data;
+        RawNbt::decode(&mut rest).unwrap();
+        assert!(!rest.is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_decompressed_structure() {
+        let blob = mansion();
+        assert!(matches!(blob["blocks"], Value::List(_)));
+
+        let data = blob.to_bytes().unwrap();
+        let limits = NbtLimits::with_max_size(64 * MAX_NBT_SIZE);
+        assert_eq!(nbt_size(&data, limits).unwrap(), data.len());
+        assert_eq!(Blob::from_reader(&mut &data[..]).unwrap(), blob);
+
+        // Too large to come from a client once each value is counted.
+        assert!(Blob::decode(&mut &data[..]).is_err());
+        assert!(RawNbt::decode(&mut &data[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_nbt_beyond_the_limits() {
+        let mut limited = DIMENSION_CODEC;
+        assert!(decode_nbt::<Value>(&mut limited, NbtLimits::with_max_size(1024)).is_err());
+
+        let mut bomb: &[u8] = &[10, 0, 0, 7, 0, 1, b'a', 0x7F, 0xFF, 0xFF, 0xFF, 0];
+        assert!(Blob::decode(&mut bomb).is_err());
+        let mut bomb: &[u8] = &[9, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert!(Value::decode(&mut bomb).is_err());
+        let mut bomb: &[u8] = &[9, 0, 0, 0, 0, 0, 0, 1];
+        assert!(RawNbt::decode(&mut bomb).is_err());
+    }
+
+    #[test]
+    fn rejects_a_root_without_a_value() {
+        let mut data: &[u8] = &[0];
+        assert!(Value::decode(&mut data).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+/// Protocol state a packet belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PacketState {
+    Handshake,
+    Status,
+    Login,
+    Play,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PacketDirection {
+    /// Sent by the client to the server.
+    Serverbound,
+    /// Sent by the server to the client.
+    Clientbound,
+}
+
+/// A packet listed in the packet registry, which implements this trait for
+/// every packet type it knows about.
+pub trait Packet {
+    /// Name of the packet in the protocol description, such as `login_start`.
+    const NAME: &'static str;
+    const STATE: PacketState;
+    const DIRECTION: PacketDirection;
+    /// Protocol version and packet id of every version that has the packet.
+    const IDS: &'static [(i32, i32)];
+
+    /// Id of the packet in protocol version `protocol`, if that version has it.
+    fn id(protocol: i32) -> Option<i32>;
+}
+
+/// Checks a `#[packet(id, state, direction)]` attribute against the registry
+/// entry of `P`, failing the build when they disagree. The id has to match the
+/// packet's id in at least one protocol version. Called by the derives.
+pub const fn check_packet_attribute<P: Packet>(
+    id: i32,
+    state: PacketState,
+    direction: PacketDirection,
+) {
+    assert!(
+        P::STATE as u8 == state as u8,
+        "#[packet] state differs from the packet registry"
+    );
+    assert!(
+        P::DIRECTION as u8 == direction as u8,
+        "#[packet] direction differs from the packet registry"
+    );
+
+    let mut i = 0;
+    while i < P::IDS.len() {
+        if P::IDS[i].1 == id {
+            return;
+        }
+        i += 1;
+    }
+    panic!("#[packet] id is not the id of this packet in any version of the packet registry");
+}
+
+impl Display for PacketState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Display for PacketDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1,121 @@
+//! Compares the synchronous `Encode`/`Decode` traits with the previous async
+//! path, which copied every frame into a `Cursor` and boxed each decoded field.
+//!
+//! Run with `cargo bench -p pandoranet`.
+
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use packet_manager::types::varint_types::VarInt;
+use pandora_utils::types::{decode_types::Decode, encode_types::Encode, packet_types::Packet};
+use pandoranet::{
+    registry::{encode_packet, LATEST_PROTOCOL},
+    structs::{handshake_struct::HandshakePacket, protocol::ClientHandshakeRequest},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    runtime::Runtime,
+};
+
+fn handshake_frame() -> Vec<u8> {
+    let mut frame = Vec::new();
+    VarInt::new(754).encode(&mut frame).unwrap();
+    "play.example.com".encode(&mut frame).unwrap();
+    25565u16.encode(&mut frame).unwrap();
+    VarInt::new(2).encode(&mut frame).unwrap();
+    frame
+}
+
+fn status_response() -> ClientHandshakeRequest {
+    ClientHandshakeRequest {
+        res_json: serde_json::json!({
+            "version": { "name": "1.16.5", "protocol": 754 },
+            "players": { "max": 100, "online": 0 },
+            "description": { "text": "A Minecraft Server" },
+        })
+        .to_string(),
+    }
+}
+
+async fn read_var_int_async(cursor: &mut Cursor<Vec<u8>>) -> Box<VarInt> {
+    let mut value = 0;
+    for i in 0..VarInt::MAX_SIZE {
+        let byte = cursor.read_u8().await.unwrap();
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Box::new(VarInt { value, len: i + 1 });
+        }
+    }
+    panic!("VarInt too long");
+}
+
+/// The pre-existing decode path: one boxed value per field, read through an
+/// async cursor over a copy of the frame.
+async fn decode_handshake_async(frame: &[u8]) -> (i32, String, u16, i32) {
+    let mut cursor = Cursor::new(frame.to_vec());
+
+    let protocol_version = read_var_int_async(&mut cursor).await;
+    let length = read_var_int_async(&mut cursor).await;
+    let mut address = vec![0u8; length.to_i32() as usize];
+    cursor.read_exact(&mut address).await.unwrap();
+    let address = Box::new(String::from_utf8(address).unwrap());
+    let mut port = [0u8; 2];
+    cursor.read_exact(&mut port).await.unwrap();
+    let port = Box::new(u16::from_be_bytes(port));
+    let next_state = read_var_int_async(&mut cursor).await;
+
+    (
+        protocol_version.to_i32(),
+        *address,
+        *port,
+        next_state.to_i32(),
+    )
+}
+
+/// The pre-existing encode path: every field written through `AsyncWrite`.
+async fn encode_status_async(packet: &ClientHandshakeRequest) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let (id, id_len) = VarInt::new(ClientHandshakeRequest::id(LATEST_PROTOCOL).unwrap()).to_bytes();
+    bytes.write_all(&id[..id_len]).await.unwrap();
+    let (len, len_len) = VarInt::new(packet.res_json.len() as i32).to_bytes();
+    bytes.write_all(&len[..len_len]).await.unwrap();
+    bytes.write_all(packet.res_json.as_bytes()).await.unwrap();
+    bytes
+}
+
+fn decode(c: &mut Criterion) {
+    let frame = handshake_frame();
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("decode_handshake");
+    group.throughput(Throughput::Bytes(frame.len() as u64));
+    group.bench_function("sync", |b| {
+        b.iter(|| {
+            let mut data = black_box(&frame[..]);
+            HandshakePacket::decode(&mut data).unwrap()
+        })
+    });
+    group.bench_function("async_cursor", |b| {
+        b.iter(|| runtime.block_on(decode_handshake_async(black_box(&frame))))
+    });
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let packet = status_response();
+    let runtime = Runtime::new().unwrap();
+    let size = encode_packet(LATEST_PROTOCOL, &packet).unwrap().len();
+
+    let mut group = c.benchmark_group("encode_status_response");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_function("sync", |b| {
+        b.iter(|| encode_packet(LATEST_PROTOCOL, black_box(&packet)).unwrap())
+    });
+    group.bench_function("async_write", |b| {
+        b.iter(|| runtime.block_on(encode_status_async(black_box(&packet))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode);
+criterion_main!(benches);
@@ -0,0 +1,289 @@
+//! Generates the packet registry from `protocol.json`, see `src/registry.rs`.
+
+use std::{collections::HashMap, env, fmt::Write, fs, path::Path};
+
+use serde::Deserialize;
+
+const PROTOCOL_FILE: &str = "protocol.json";
+
+#[derive(Deserialize)]
+struct Protocol {
+    versions: Vec<Version>,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    name: String,
+    protocol: i32,
+    packets: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    state: String,
+    direction: String,
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    ty: Option<String>,
+}
+
+/// A registry entry checked and converted to the names used in Rust.
+struct PacketEntry<'a> {
+    protocol: i32,
+    state: &'static str,
+    direction: &'static str,
+    id: i32,
+    name: &'a str,
+    ty: Option<&'a str>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", PROTOCOL_FILE);
+
+    let source = fs::read_to_string(PROTOCOL_FILE)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", PROTOCOL_FILE, e));
+    let protocol: Protocol = serde_json::from_str(&source)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", PROTOCOL_FILE, e));
+
+    let entries = check(&protocol);
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("registry.rs");
+    fs::write(out, generate(&protocol, &entries)).expect("Failed to write the packet registry");
+}
+
+fn check(protocol: &Protocol) -> Vec<PacketEntry<'_>> {
+    let mut entries = Vec::new();
+    let mut types: HashMap<&str, (&str, &str, &str)> = HashMap::new();
+
+    if protocol.versions.is_empty() {
+        panic!("{} does not describe any protocol version", PROTOCOL_FILE);
+    }
+
+    for version in &protocol.versions {
+        if protocol
+            .versions
+            .iter()
+            .filter(|v| v.protocol == version.protocol)
+            .count()
+            > 1
+        {
+            panic!("Protocol {} is described more than once", version.protocol);
+        }
+
+        for entry in &version.packets {
+            let context = format!("{} packet {}", version.name, entry.name);
+            let packet = PacketEntry {
+                protocol: version.protocol,
+                state: match entry.state.as_str() {
+                    "handshake" => "Handshake",
+                    "status" => "Status",
+                    "login" => "Login",
+                    "play" => "Play",
+                    other => panic!("{}: unknown state {}", context, other),
+                },
+                direction: match entry.direction.as_str() {
+                    "serverbound" => "Serverbound",
+                    "clientbound" => "Clientbound",
+                    other => panic!("{}: unknown direction {}", context, other),
+                },
+                id: parse_id(&entry.id)
+                    .unwrap_or_else(|| panic!("{}: invalid id {}", context, entry.id)),
+                name: &entry.name,
+                ty: entry.ty.as_deref(),
+            };
+
+            if entries.iter().any(|other: &PacketEntry| {
+                other.protocol == packet.protocol
+                    && other.state == packet.state
+                    && other.direction == packet.direction
+                    && (other.id == packet.id || other.name == packet.name)
+            }) {
+                panic!("{}: the id or name is already used in this state", context);
+            }
+
+            // A type implements `Packet` once, so it has to keep its name, state
+            // and direction in every version.
+            if let Some(ty) = packet.ty {
+                let key = (packet.name, packet.state, packet.direction);
+                if *types.entry(ty).or_insert(key) != key {
+                    panic!(
+                        "{}: {} is already registered as another packet",
+                        context, ty
+                    );
+                }
+            }
+
+            entries.push(packet);
+        }
+    }
+
+    entries
+}
+
+fn parse_id(id: &str) -> Option<i32> {
+    match id.strip_prefix("0x") {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => id.parse().ok(),
+    }
+}
+
+fn generate(protocol: &Protocol, entries: &[PacketEntry]) -> String {
+    let mut out = String::new();
+    let latest = protocol.versions.iter().map(|v| v.protocol).max().unwrap();
+
+    let mut versions: Vec<&Version> = protocol.versions.iter().collect();
+    versions.sort_by_key(|version| version.protocol);
+
+    writeln!(
+        out,
+        "/// Protocol versions described in `protocol.json`, oldest first."
+    )
+    .unwrap();
+    writeln!(out, "pub const VERSIONS: &[ProtocolVersion] = &[").unwrap();
+    for version in versions {
+        writeln!(
+            out,
+            "    ProtocolVersion {{ name: {:?}, protocol: {} }},",
+            version.name, version.protocol
+        )
+        .unwrap();
+    }
+    writeln!(out, "];\n").unwrap();
+    writeln!(out, "/// The newest protocol version in `protocol.json`.").unwrap();
+    writeln!(out, "pub const LATEST_PROTOCOL: i32 = {};\n", latest).unwrap();
+
+    // Packet impls, one per type with the ids of every version it appears in.
+    let mut typed: Vec<&PacketEntry> = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.ty.is_some()) {
+        if !typed.iter().any(|other| other.ty == entry.ty) {
+            typed.push(entry);
+        }
+    }
+
+    for packet in &typed {
+        let ty = packet.ty.unwrap();
+        writeln!(out, "impl Packet for crate::{} {{", ty).unwrap();
+        writeln!(out, "    const NAME: &'static str = {:?};", packet.name).unwrap();
+        writeln!(
+            out,
+            "    const STATE: PacketState = PacketState::{};",
+            packet.state
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    const DIRECTION: PacketDirection = PacketDirection::{};",
+            packet.direction
+        )
+        .unwrap();
+        let ids: Vec<String> = entries
+            .iter()
+            .filter(|entry| entry.ty == packet.ty)
+            .map(|entry| format!("({}, {:#04x})", entry.protocol, entry.id))
+            .collect();
+        writeln!(
+            out,
+            "    const IDS: &'static [(i32, i32)] = &[{}];\n",
+            ids.join(", ")
+        )
+        .unwrap();
+        writeln!(out, "    fn id(protocol: i32) -> Option<i32> {{").unwrap();
+        writeln!(out, "        match protocol {{").unwrap();
+        for entry in entries.iter().filter(|entry| entry.ty == packet.ty) {
+            writeln!(
+                out,
+                "            {} => Some({:#04x}),",
+                entry.protocol, entry.id
+            )
+            .unwrap();
+        }
+        writeln!(out, "            _ => None,").unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}\n").unwrap();
+    }
+
+    // Dispatch of serverbound packets by id.
+    writeln!(
+        out,
+        "/// Decodes the serverbound packet `packet_id` of `state` and passes it to its\n\
+         /// handler. Returns `false` when the registry has no type for the packet or\n\
+         /// the type has no handler."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub async fn dispatch_serverbound(\n    protocol: i32,\n    state: PacketState,\n    \
+         packet_id: i32,\n    data: &mut &[u8],\n    conn: &mut Connection,\n    \
+         server: &ServerContext,\n) -> Result<bool, ProtocolError> {{"
+    )
+    .unwrap();
+    writeln!(out, "    let handlers = server.handlers;\n").unwrap();
+    writeln!(out, "    match (protocol, state, packet_id) {{").unwrap();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.ty.is_some() && entry.direction == "Serverbound")
+    {
+        writeln!(
+            out,
+            "        ({}, PacketState::{}, {:#04x}) => {{\n            handlers\n                \
+             .dispatch::<crate::{}>(packet_id, data, conn, server)\n                .await\n        }}",
+            entry.protocol,
+            entry.state,
+            entry.id,
+            entry.ty.unwrap()
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => Ok(false),").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    // Name lookups, which also cover packets that have no type yet.
+    writeln!(
+        out,
+        "/// Id of the packet called `name` in `protocol.json`, including packets\n\
+         /// without a Rust type."
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub fn packet_id(\n    protocol: i32,\n    state: PacketState,\n    direction: PacketDirection,\n    \
+         name: &str,\n) -> Option<i32> {{"
+    )
+    .unwrap();
+    writeln!(out, "    match (protocol, state, direction, name) {{").unwrap();
+    for entry in entries {
+        writeln!(
+            out,
+            "        ({}, PacketState::{}, PacketDirection::{}, {:?}) => Some({:#04x}),",
+            entry.protocol, entry.state, entry.direction, entry.name, entry.id
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => None,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "/// Name of packet `packet_id` in `protocol.json`.").unwrap();
+    writeln!(
+        out,
+        "pub fn packet_name(\n    protocol: i32,\n    state: PacketState,\n    direction: PacketDirection,\n    \
+         packet_id: i32,\n) -> Option<&'static str> {{"
+    )
+    .unwrap();
+    writeln!(out, "    match (protocol, state, direction, packet_id) {{").unwrap();
+    for entry in entries {
+        writeln!(
+            out,
+            "        ({}, PacketState::{}, PacketDirection::{}, {:#04x}) => Some({:?}),",
+            entry.protocol, entry.state, entry.direction, entry.id, entry.name
+        )
+        .unwrap();
+    }
+    writeln!(out, "        _ => None,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    out
+}
@@ -0,0 +1,85 @@
+//! Player actions passed from connection tasks to the game loop.
+//!
+//! Packet handlers run on the task of their connection, so anything that
+//! changes shared game state is sent to `PLAYER_ACTIONS` instead and applied by
+//! the game loop on its next tick, in the order it was received.
+
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::debug;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::OnlinePlayer;
+
+lazy_static! {
+    pub static ref PLAYER_ACTIONS: ActionQueue = ActionQueue::new();
+}
+
+#[derive(Debug)]
+pub enum PlayerAction {
+    /// A player finished logging in and was sent Join Game.
+    Joined { uuid: Uuid, player: OnlinePlayer },
+    /// The connection of a player ended.
+    Left { uuid: Uuid, connection_id: u32 },
+    /// The client arrived where the teleport with this id sent it.
+    TeleportConfirmed {
+        uuid: Uuid,
+        connection_id: u32,
+        teleport_id: i32,
+    },
+    /// The client moved or looked around.
+    Moved {
+        uuid: Uuid,
+        connection_id: u32,
+        movement: Movement,
+    },
+}
+
+/// Movement reported by a client, already checked for invalid numbers and
+/// clamped to the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    /// Feet position, when the packet carried one.
+    pub position: Option<(f64, f64, f64)>,
+    /// Yaw and pitch in degrees, when the packet carried them.
+    pub rotation: Option<(f32, f32)>,
+    pub on_ground: bool,
+}
+
+/// Carries actions to the game loop. The queue is unbounded so that actions can
+/// be sent from synchronous code, like connection cleanup, and is drained every
+/// tick.
+pub struct ActionQueue {
+    sender: mpsc::UnboundedSender<PlayerAction>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<PlayerAction>>>,
+}
+
+impl ActionQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    pub fn send(&self, action: PlayerAction) {
+        if let Err(e) = self.sender.send(action) {
+            debug!("Game loop stopped, dropping {:?}", e.0);
+        }
+    }
+
+    /// Takes the receiving end, which only the game loop should hold. Returns
+    /// `None` once it has been taken.
+    pub fn take_receiver(&self) -> Option<mpsc::UnboundedReceiver<PlayerAction>> {
+        self.receiver.lock().unwrap().take()
+    }
+}
+
+impl Default for ActionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
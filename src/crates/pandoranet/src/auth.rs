@@ -0,0 +1,245 @@
+use lazy_static::lazy_static;
+use log::{debug, info};
+use num_bigint::BigInt;
+use pandora_macros::Encode;
+use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// How long connecting to the session server may take.
+const SESSION_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a `hasJoined` request may take in total, the player waits in the
+/// login screen meanwhile.
+const SESSION_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref SERVER_KEY: ServerKey = ServerKey::generate();
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .connect_timeout(SESSION_CONNECT_TIMEOUT)
+        .timeout(SESSION_REQUEST_TIMEOUT)
+        .build()
+        .expect("Failed to build the session server client");
+}
+
+/// Errors raised while authenticating a player in online mode.
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("failed to decrypt: {0}")]
+    Decrypt(#[from] rsa::Error),
+
+    #[error("session server request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("session server returned {0}")]
+    Status(reqwest::StatusCode),
+
+    #[error("invalid profile id {id}: {source}")]
+    InvalidProfileId { id: String, source: uuid::Error },
+}
+
+/// The RSA key pair used for the login encryption handshake.
+pub struct ServerKey {
+    private_key: RsaPrivateKey,
+    /// The public key in the ASN.1 DER form sent in Encryption Request.
+    pub public_key_der: Vec<u8>,
+}
+
+impl ServerKey {
+    fn generate() -> Self {
+        let start_time = Instant::now();
+
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)
+            .expect("Failed to generate server key pair");
+        let public_key_der = RsaPublicKey::from(&private_key)
+            .to_public_key_der()
+            .expect("Failed to encode server public key")
+            .as_bytes()
+            .to_vec();
+
+        info!("Generated server key pair in {:?}", start_time.elapsed());
+
+        Self {
+            private_key,
+            public_key_der,
+        }
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, AuthError> {
+        Ok(self.private_key.decrypt(Pkcs1v15Encrypt, data)?)
+    }
+}
+
+/// Returns the server key pair, generating it on first use.
+pub fn server_key() -> &'static ServerKey {
+    &SERVER_KEY
+}
+
+/// Computes the server hash sent to `hasJoined`: the SHA-1 digest of the server id,
+/// shared secret and public key, printed as a signed two's complement hex number.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+
+    BigInt::from_signed_bytes_be(&hasher.finalize()).to_str_radix(16)
+}
+
+/// A signed profile property, such as the skin and cape under `textures`. Sent
+/// to clients as it is in Player Info.
+#[derive(Deserialize, Encode, Debug, Clone, PartialEq)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ProfileResponse {
+    id: String,
+    name: String,
+    #[serde(default)]
+    properties: Vec<ProfileProperty>,
+}
+
+/// A player profile confirmed by the session server.
+#[derive(Debug, Clone)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// Asks the session server whether `username` joined the server identified by
+/// `server_hash`. Returns `Ok(None)` if the session server does not know the player.
+/// Fails once the request takes longer than `SESSION_REQUEST_TIMEOUT`.
+pub async fn has_joined(
+    session_server: &str,
+    username: &str,
+    server_hash: &str,
+) -> Result<Option<GameProfile>, AuthError> {
+    let url = format!(
+        "{}/session/minecraft/hasJoined",
+        session_server.trim_end_matches('/')
+    );
+
+    let response = HTTP_CLIENT
+        .get(&url)
+        .query(&[("username", username), ("serverId", server_hash)])
+        .send()
+        .await?;
+
+    debug!("hasJoined for {} returned {}", username, response.status());
+
+    if response.status() == reqwest::StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+
+    if !response.status().is_success() {
+        return Err(AuthError::Status(response.status()));
+    }
+
+    let profile: ProfileResponse = response.json().await?;
+
+    let id = Uuid::try_parse(&profile.id).map_err(|source| AuthError::InvalidProfileId {
+        id: profile.id.clone(),
+        source,
+    })?;
+
+    Ok(Some(GameProfile {
+        id,
+        name: profile.name,
+        properties: profile.properties,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::mock_http_server;
+
+    #[test]
+    fn hashes_like_the_vanilla_server() {
+        assert_eq!(
+            server_hash("Notch", &[], &[]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            server_hash("jeb_", &[], &[]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            server_hash("simon", &[], &[]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+    }
+
+    #[test]
+    fn decrypts_what_the_client_encrypted() {
+        let key = server_key();
+        let public_key = RsaPublicKey::from(&key.private_key);
+        let secret = [7u8; 16];
+
+        let encrypted = public_key
+            .encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, &secret)
+            .unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), secret);
+        assert!(matches!(
+            key.decrypt(&[0u8; 128]),
+            Err(AuthError::Decrypt(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fails_on_a_session_server_error() {
+        let (url, _) = mock_http_server(
+            "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\n\r\n".to_string(),
+        )
+        .await;
+
+        let result = has_joined(&url, "Tester", "hash").await;
+        assert!(matches!(
+            result,
+            Err(AuthError::Status(reqwest::StatusCode::SERVICE_UNAVAILABLE))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reads_the_profile_of_a_joined_player() {
+        let body = r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"e30=","signature":"c2ln"}]}"#;
+        let (url, request) = mock_http_server(format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+        .await;
+
+        let profile = has_joined(&url, "notch", "-1a2b").await.unwrap().unwrap();
+        assert_eq!(
+            profile.id,
+            Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+        );
+        assert_eq!(profile.name, "Notch");
+        assert_eq!(
+            profile.properties,
+            [ProfileProperty {
+                name: "textures".to_string(),
+                value: "e30=".to_string(),
+                signature: Some("c2ln".to_string()),
+            }]
+        );
+        assert_eq!(
+            request.await.unwrap(),
+            "GET /session/minecraft/hasJoined?username=notch&serverId=-1a2b HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_nothing_for_an_unknown_session() {
+        let (url, _) = mock_http_server("HTTP/1.1 204 No Content\r\n\r\n".to_string()).await;
+        assert!(has_joined(&url, "Tester", "hash").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// AES-128 in 8-bit cipher feedback mode, the stream cipher used by the protocol.
+///
+/// The shared secret from the login handshake is used as both key and IV.
+pub struct Cfb8Cipher {
+    aes: Aes128,
+    shift_register: [u8; 16],
+}
+
+impl Cfb8Cipher {
+    pub fn new(shared_secret: &[u8]) -> Result<Self, Error> {
+        let aes = Aes128::new_from_slice(shared_secret)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Shared secret must be 16 bytes"))?;
+
+        let mut shift_register = [0u8; 16];
+        shift_register.copy_from_slice(shared_secret);
+
+        Ok(Self {
+            aes,
+            shift_register,
+        })
+    }
+
+    #[inline]
+    fn next_key_byte(&self) -> u8 {
+        let mut block = GenericArray::from(self.shift_register);
+        self.aes.encrypt_block(&mut block);
+        block[0]
+    }
+
+    #[inline]
+    fn feed_back(&mut self, ciphertext: u8) {
+        self.shift_register.copy_within(1.., 0);
+        self.shift_register[15] = ciphertext;
+    }
+
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte ^= self.next_key_byte();
+            self.feed_back(*byte);
+        }
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data {
+            let ciphertext = *byte;
+            *byte ^= self.next_key_byte();
+            self.feed_back(ciphertext);
+        }
+    }
+}
+
+impl fmt::Debug for Cfb8Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cfb8Cipher")
+    }
+}
+
+/// Read half wrapper that decrypts everything read once encryption is enabled.
+#[derive(Debug)]
+pub struct CipherReader<R> {
+    inner: R,
+    cipher: Option<Cfb8Cipher>,
+}
+
+impl<R> CipherReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            cipher: None,
+        }
+    }
+
+    /// Decrypts all bytes read from now on. `buffered` holds bytes that were
+    /// already read from the socket but arrived after the client enabled encryption.
+    pub fn enable_encryption(
+        &mut self,
+        shared_secret: &[u8],
+        buffered: &mut [u8],
+    ) -> Result<(), Error> {
+        let mut cipher = Cfb8Cipher::new(shared_secret)?;
+        cipher.decrypt(buffered);
+        self.cipher = Some(cipher);
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CipherReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let already_filled = buf.filled().len();
+
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        if let Some(cipher) = &mut this.cipher {
+            cipher.decrypt(&mut buf.filled_mut()[already_filled..]);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Write half wrapper that encrypts everything written once encryption is enabled.
+///
+/// Encrypted bytes the socket did not accept yet are kept until the next write
+/// or flush, so callers must flush after writing a packet.
+#[derive(Debug)]
+pub struct CipherWriter<W> {
+    inner: W,
+    cipher: Option<Cfb8Cipher>,
+    pending: Vec<u8>,
+}
+
+impl<W> CipherWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            cipher: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Encrypts all bytes written from now on.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<(), Error> {
+        self.cipher = Some(Cfb8Cipher::new(shared_secret)?);
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> CipherWriter<W> {
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CipherWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+
+        let Some(cipher) = &mut this.cipher else {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        };
+
+        // The cipher state advances as bytes are encrypted, so once encrypted they
+        // are owned by the writer and sent on a later poll if the socket is busy.
+        this.pending.extend_from_slice(buf);
+        cipher.encrypt(&mut this.pending);
+        if let Poll::Ready(Err(e)) = this.poll_write_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Buf, BytesMut};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    const SECRET: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+
+    /// A Login Start frame for `TestPlayer`.
+    const PLAINTEXT: &[u8] = b"\x0b\x00\x09TestPlayer";
+    /// `PLAINTEXT` encrypted by the vanilla client, which uses Java's
+    /// `AES/CFB8/NoPadding` with the shared secret as key and IV.
+    const CIPHERTEXT: &[u8] = &[
+        0x01, 0xC4, 0xA2, 0xE9, 0x4B, 0x65, 0xF7, 0x96, 0x68, 0x3F, 0xB3, 0x4E, 0x5E,
+    ];
+
+    #[test]
+    fn encrypts_like_the_vanilla_client() {
+        let mut data = PLAINTEXT.to_vec();
+        Cfb8Cipher::new(&SECRET).unwrap().encrypt(&mut data);
+        assert_eq!(data, CIPHERTEXT);
+
+        // The NIST SP 800-38A CFB8 key, used as its own IV.
+        let secret = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let mut data = vec![
+            0x6B, 0xC1, 0xBE, 0xE2, 0x2E, 0x40, 0x9F, 0x96, 0xE9, 0x3D, 0x7E, 0x11, 0x73, 0x93,
+            0x17, 0x2A, 0xAE, 0x2D,
+        ];
+        Cfb8Cipher::new(&secret).unwrap().encrypt(&mut data);
+        assert_eq!(
+            data,
+            [
+                0x14, 0x11, 0x8E, 0x07, 0x10, 0xEB, 0x1B, 0x4C, 0xA3, 0x0A, 0xE2, 0xDE, 0x24, 0x74,
+                0x7E, 0xFB, 0x61, 0x73
+            ]
+        );
+    }
+
+    #[test]
+    fn decrypts_like_the_vanilla_client() {
+        let mut data = CIPHERTEXT.to_vec();
+        Cfb8Cipher::new(&SECRET).unwrap().decrypt(&mut data);
+        assert_eq!(data, PLAINTEXT);
+    }
+
+    #[test]
+    fn keeps_its_state_across_calls() {
+        let mut data = CIPHERTEXT.to_vec();
+        let mut cipher = Cfb8Cipher::new(&SECRET).unwrap();
+        let (first, rest) = data.split_at_mut(5);
+        cipher.decrypt(first);
+        cipher.decrypt(rest);
+        assert_eq!(data, PLAINTEXT);
+    }
+
+    #[test]
+    fn rejects_a_short_secret() {
+        let error = Cfb8Cipher::new(&SECRET[..15]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn decrypts_bytes_buffered_before_encryption_was_enabled() {
+        let response = [0x02, 0x01, 0xFF];
+        let mut encrypted = PLAINTEXT.repeat(2);
+        Cfb8Cipher::new(&SECRET).unwrap().encrypt(&mut encrypted);
+
+        // The client sends its Encryption Response in plain text and encrypts
+        // everything after it.
+        let mut stream = response.to_vec();
+        stream.extend_from_slice(&encrypted);
+        let mut reader = CipherReader::new(&stream[..]);
+
+        // The first read returns the response and part of the encrypted data.
+        let mut buffer = BytesMut::new();
+        let mut first = [0u8; 8];
+        reader.read_exact(&mut first).await.unwrap();
+        buffer.extend_from_slice(&first);
+        assert_eq!(&buffer[..3], response);
+        buffer.advance(3);
+
+        reader.enable_encryption(&SECRET, &mut buffer).unwrap();
+        assert!(reader.is_encrypted());
+        reader.read_buf(&mut buffer).await.unwrap();
+
+        assert_eq!(&buffer[..], PLAINTEXT.repeat(2));
+    }
+
+    #[tokio::test]
+    async fn writer_encrypts_only_after_encryption_is_enabled() {
+        let mut writer = CipherWriter::new(Vec::new());
+        writer.write_all(b"plain").await.unwrap();
+        writer.enable_encryption(&SECRET).unwrap();
+        writer.write_all(&PLAINTEXT[..4]).await.unwrap();
+        writer.write_all(&PLAINTEXT[4..]).await.unwrap();
+        writer.flush().await.unwrap();
+
+        assert_eq!(&writer.inner[..5], b"plain");
+        assert_eq!(&writer.inner[5..], CIPHERTEXT);
+    }
+}
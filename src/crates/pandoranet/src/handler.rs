@@ -0,0 +1,288 @@
+//! Handlers for serverbound packets.
+//!
+//! Every serverbound packet type in the registry can have one handler. The
+//! built-in handlers are registered in `PACKET_HANDLERS` on first use, and other
+//! crates can add handlers for packets the server does not handle yet, or
+//! replace the built-in ones, before the server starts:
+//!
+//! ```ignore
+//! struct ChatHandler;
+//!
+//! #[async_trait]
+//! impl PacketHandler<ChatMessagePacket> for ChatHandler {
+//!     async fn handle(
+//!         &self,
+//!         packet: ChatMessagePacket,
+//!         conn: &mut Connection,
+//!         server: &ServerContext,
+//!     ) -> Result<(), ProtocolError> {
+//!         info!("<{}> {}", conn.player_name.as_deref().unwrap_or("?"), packet.message);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! PACKET_HANDLERS.register(ChatHandler);
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use packet_manager::ProtocolError;
+use pandora_utils::{
+    config::Config,
+    types::{decode_types::Decode, packet_types::Packet},
+};
+
+use crate::structs::{
+    handshake_struct::HandshakeHandler,
+    login_struct::{EncryptionResponseHandler, LoginStartHandler},
+    play_struct::{
+        KeepAliveHandler, MovementHandler, PlayerOnGroundPacket, PlayerPositionAndRotationPacket,
+        PlayerPositionPacket, PlayerRotationPacket, TeleportConfirmHandler,
+    },
+    status_struct::{PingHandler, StatusRequestHandler},
+};
+use crate::{Connection, ConnectionManager, CONNECTION_MANAGER};
+
+lazy_static! {
+    pub static ref PACKET_HANDLERS: HandlerRegistry = HandlerRegistry::with_defaults();
+}
+
+/// Handles one serverbound packet type.
+#[async_trait]
+pub trait PacketHandler<P>: Send + Sync + 'static {
+    /// Handles `packet`, received on `conn`. An error disconnects the client.
+    async fn handle(
+        &self,
+        packet: P,
+        conn: &mut Connection,
+        server: &ServerContext,
+    ) -> Result<(), ProtocolError>;
+}
+
+/// State shared by every connection, passed to packet handlers.
+pub struct ServerContext {
+    /// The configuration active when the packet was received.
+    pub config: Arc<Config>,
+    pub connections: &'static ConnectionManager,
+    pub handlers: &'static HandlerRegistry,
+}
+
+impl ServerContext {
+    pub fn current() -> Self {
+        Self {
+            config: Config::get(),
+            connections: &CONNECTION_MANAGER,
+            handlers: &PACKET_HANDLERS,
+        }
+    }
+}
+
+/// The handler of each serverbound packet type, keyed by the packet's `TypeId`.
+pub struct HandlerRegistry {
+    /// Each value is an `Arc<dyn PacketHandler<P>>` for the packet type `P`.
+    handlers: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
+
+impl HandlerRegistry {
+    /// A registry without any handlers.
+    pub fn new() -> Self {
+        Self {
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// A registry with the handlers for the handshake, status and login packets,
+    /// and for Keep Alive, Teleport Confirm and movement.
+    pub fn with_defaults() -> Self {
+        let registry = Self::new();
+        registry.register(HandshakeHandler);
+        registry.register(StatusRequestHandler);
+        registry.register(PingHandler);
+        registry.register(LoginStartHandler);
+        registry.register(EncryptionResponseHandler);
+        registry.register(KeepAliveHandler);
+        registry.register(TeleportConfirmHandler);
+        registry.register::<PlayerPositionPacket, _>(MovementHandler);
+        registry.register::<PlayerPositionAndRotationPacket, _>(MovementHandler);
+        registry.register::<PlayerRotationPacket, _>(MovementHandler);
+        registry.register::<PlayerOnGroundPacket, _>(MovementHandler);
+        registry
+    }
+
+    /// Makes `handler` the handler of packet `P`, replacing the previous one.
+    pub fn register<P, H>(&self, handler: H)
+    where
+        P: Packet + Send + 'static,
+        H: PacketHandler<P>,
+    {
+        let handler: Arc<dyn PacketHandler<P>> = Arc::new(handler);
+        self.handlers
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<P>(), Box::new(handler));
+    }
+
+    /// Removes the handler of packet `P`, which is then logged as unhandled.
+    pub fn unregister<P: Packet + 'static>(&self) {
+        self.handlers.write().unwrap().remove(&TypeId::of::<P>());
+    }
+
+    pub fn get<P: 'static>(&self) -> Option<Arc<dyn PacketHandler<P>>> {
+        self.handlers
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<P>())
+            .and_then(|handler| handler.downcast_ref::<Arc<dyn PacketHandler<P>>>())
+            .cloned()
+    }
+
+    /// Decodes packet `P` from `data` and passes it to its handler. Returns
+    /// `false` without reading `data` when `P` has no handler.
+    pub async fn dispatch<P>(
+        &self,
+        packet_id: i32,
+        data: &mut &[u8],
+        conn: &mut Connection,
+        server: &ServerContext,
+    ) -> Result<bool, ProtocolError>
+    where
+        P: for<'a> Decode<'a> + Send + 'static,
+    {
+        let Some(handler) = self.get::<P>() else {
+            return Ok(false);
+        };
+
+        let packet = P::decode(data).map_err(|e| e.in_packet(packet_id))?;
+        handler.handle(packet, conn, server).await?;
+
+        Ok(true)
+    }
+}
+
+impl Default for HandlerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::{sync::mpsc, time::timeout};
+
+    use super::*;
+    use crate::structs::play_struct::{ChatMessagePacket, KeepAlivePacket};
+    use crate::test_util::{connect, idle_connection, lock_config};
+
+    /// Counts the packets it handles.
+    struct Counter(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl PacketHandler<KeepAlivePacket> for Counter {
+        async fn handle(
+            &self,
+            _packet: KeepAlivePacket,
+            _conn: &mut Connection,
+            _server: &ServerContext,
+        ) -> Result<(), ProtocolError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Passes on every chat message with the name of its sender.
+    struct ChatLog(mpsc::UnboundedSender<(Option<String>, String)>);
+
+    #[async_trait]
+    impl PacketHandler<ChatMessagePacket> for ChatLog {
+        async fn handle(
+            &self,
+            packet: ChatMessagePacket,
+            conn: &mut Connection,
+            _server: &ServerContext,
+        ) -> Result<(), ProtocolError> {
+            self.0
+                .send((conn.player_name.clone(), packet.message))
+                .unwrap();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn registering_replaces_the_default_handler() {
+        let (mut conn, _client) = idle_connection().await;
+        let server = ServerContext::current();
+        let registry = HandlerRegistry::with_defaults();
+        let calls = Arc::new(AtomicUsize::new(0));
+        registry.register(Counter(calls.clone()));
+
+        let data = 42i64.to_be_bytes();
+        let mut data = &data[..];
+        let handled = registry
+            .dispatch::<KeepAlivePacket>(0x10, &mut data, &mut conn, &server)
+            .await
+            .unwrap();
+
+        assert!(handled);
+        assert!(data.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unregistering_leaves_the_packet_unhandled() {
+        let (mut conn, _client) = idle_connection().await;
+        let server = ServerContext::current();
+        let registry = HandlerRegistry::with_defaults();
+        assert!(registry.get::<KeepAlivePacket>().is_some());
+
+        registry.unregister::<KeepAlivePacket>();
+        assert!(registry.get::<KeepAlivePacket>().is_none());
+
+        let data = 42i64.to_be_bytes();
+        let mut data = &data[..];
+        let handled = registry
+            .dispatch::<KeepAlivePacket>(0x10, &mut data, &mut conn, &server)
+            .await
+            .unwrap();
+        assert!(!handled);
+        assert_eq!(data.len(), 8, "an unhandled packet is not read");
+
+        // Chat has no default handler to fall back to either.
+        let mut data = &[0x02, b'h', b'i'][..];
+        let handled = registry
+            .dispatch::<ChatMessagePacket>(0x03, &mut data, &mut conn, &server)
+            .await
+            .unwrap();
+        assert!(!handled);
+    }
+
+    #[tokio::test]
+    async fn connections_pass_packets_to_registered_handlers() {
+        let _config = lock_config().await;
+        let (sender, mut messages) = mpsc::unbounded_channel();
+        PACKET_HANDLERS.register(ChatLog(sender));
+
+        let (mut client, _handle, _task) = connect().await;
+        client.handshake(2).await;
+        client.send(0x00, &[&"Chatter".to_string()]).await;
+        assert_eq!(client.receive().await.0, 0x02);
+        client.send(0x03, &[&"hello".to_string()]).await;
+
+        let message = timeout(Duration::from_secs(5), messages.recv()).await;
+        PACKET_HANDLERS.unregister::<ChatMessagePacket>();
+        assert_eq!(
+            message.unwrap(),
+            Some((Some("Chatter".to_string()), "hello".to_string()))
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Keep Alive scheduling for connections in play.
+//!
+//! Clients disconnect when the server stops sending Keep Alive, and the server
+//! drops clients that stop answering it. Time is read from `tokio::time`, so
+//! tests can drive a connection with a paused clock and shorter
+//! [`KeepAliveTiming`].
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rand::random;
+use tokio::time::Instant;
+
+/// How often a Keep Alive is sent.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// How long the client has to answer a Keep Alive.
+pub const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAliveTiming {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for KeepAliveTiming {
+    fn default() -> Self {
+        Self {
+            interval: KEEP_ALIVE_INTERVAL,
+            timeout: KEEP_ALIVE_TIMEOUT,
+        }
+    }
+}
+
+/// The round trip time of a connection, shared with every copy of its handle
+/// and player.
+#[derive(Debug, Clone, Default)]
+pub struct Latency(Arc<AtomicU32>);
+
+impl Latency {
+    pub fn get(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed) as u64)
+    }
+
+    /// The latency in milliseconds, as the tab list shows it.
+    pub fn millis(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set_millis(&self, millis: u32) {
+        self.0.store(millis, Ordering::Relaxed);
+    }
+}
+
+/// What the connection has to do when a keep alive deadline passes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeepAliveAction {
+    /// Send a Keep Alive with this id.
+    Send(i64),
+    /// The client did not answer the outstanding Keep Alive in time.
+    TimedOut,
+    Wait,
+}
+
+/// Keep Alive state of one connection. Nothing is sent until [`KeepAlive::start`].
+#[derive(Debug)]
+pub struct KeepAlive {
+    timing: KeepAliveTiming,
+    next_send: Option<Instant>,
+    /// Id and send time of the Keep Alive awaiting a response.
+    pending: Option<(i64, Instant)>,
+    latency: Latency,
+}
+
+impl KeepAlive {
+    pub fn new(timing: KeepAliveTiming) -> Self {
+        Self {
+            timing,
+            next_send: None,
+            pending: None,
+            latency: Latency::default(),
+        }
+    }
+
+    /// Schedules the first Keep Alive one interval after `now`.
+    pub fn start(&mut self, now: Instant) {
+        if self.next_send.is_none() {
+            self.next_send = Some(now + self.timing.interval);
+        }
+    }
+
+    /// When [`KeepAlive::poll`] has something to do next, if anything.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.pending {
+            Some((_, sent)) => Some(sent + self.timing.timeout),
+            None => self.next_send,
+        }
+    }
+
+    pub fn poll(&mut self, now: Instant) -> KeepAliveAction {
+        if let Some((_, sent)) = self.pending {
+            if now >= sent + self.timing.timeout {
+                return KeepAliveAction::TimedOut;
+            }
+            return KeepAliveAction::Wait;
+        }
+
+        match self.next_send {
+            Some(next_send) if now >= next_send => {
+                let id = random::<i64>();
+                self.pending = Some((id, now));
+                self.next_send = Some(now + self.timing.interval);
+                KeepAliveAction::Send(id)
+            }
+            _ => KeepAliveAction::Wait,
+        }
+    }
+
+    /// Records a Keep Alive response. Returns the round trip time when `id`
+    /// matches the outstanding Keep Alive, other ids are ignored.
+    pub fn acknowledge(&mut self, id: i64, now: Instant) -> Option<Duration> {
+        match self.pending {
+            Some((pending, sent)) if pending == id => {
+                self.pending = None;
+                let round_trip = now - sent;
+
+                // Smoothed like the vanilla server, so one slow response does
+                // not make the tab list jump.
+                let millis = u32::try_from(round_trip.as_millis()).unwrap_or(u32::MAX);
+                let latency = (self.latency.millis() as u64 * 3 + millis as u64) / 4;
+                self.latency.set_millis(latency as u32);
+
+                Some(round_trip)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn latency(&self) -> &Latency {
+        &self.latency
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new(KeepAliveTiming::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::{advance, pause};
+
+    use super::*;
+
+    const TIMING: KeepAliveTiming = KeepAliveTiming {
+        interval: Duration::from_secs(15),
+        timeout: Duration::from_secs(30),
+    };
+
+    fn started() -> KeepAlive {
+        let mut keep_alive = KeepAlive::new(TIMING);
+        keep_alive.start(Instant::now());
+        keep_alive
+    }
+
+    fn sent_id(keep_alive: &mut KeepAlive) -> i64 {
+        match keep_alive.poll(Instant::now()) {
+            KeepAliveAction::Send(id) => id,
+            other => panic!("expected a keep alive, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_nothing_before_it_is_started() {
+        pause();
+        let mut keep_alive = KeepAlive::new(TIMING);
+
+        advance(Duration::from_secs(600)).await;
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::Wait);
+        assert_eq!(keep_alive.deadline(), None);
+    }
+
+    #[tokio::test]
+    async fn sends_once_per_interval() {
+        pause();
+        let start = Instant::now();
+        let mut keep_alive = started();
+        assert_eq!(keep_alive.deadline(), Some(start + TIMING.interval));
+
+        advance(TIMING.interval - Duration::from_millis(1)).await;
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::Wait);
+
+        advance(Duration::from_millis(1)).await;
+        let id = sent_id(&mut keep_alive);
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::Wait);
+        assert_eq!(keep_alive.deadline(), Some(Instant::now() + TIMING.timeout));
+
+        advance(Duration::from_millis(40)).await;
+        assert_eq!(
+            keep_alive.acknowledge(id, Instant::now()),
+            Some(Duration::from_millis(40))
+        );
+        assert_eq!(keep_alive.deadline(), Some(start + TIMING.interval * 2));
+
+        advance(TIMING.interval).await;
+        assert_ne!(sent_id(&mut keep_alive), id);
+    }
+
+    #[tokio::test]
+    async fn ignores_unknown_and_repeated_ids() {
+        pause();
+        let mut keep_alive = started();
+        assert_eq!(keep_alive.acknowledge(1, Instant::now()), None);
+
+        advance(TIMING.interval).await;
+        let id = sent_id(&mut keep_alive);
+        assert_eq!(
+            keep_alive.acknowledge(id.wrapping_add(1), Instant::now()),
+            None
+        );
+        assert_eq!(keep_alive.latency().millis(), 0);
+
+        advance(Duration::from_millis(100)).await;
+        assert!(keep_alive.acknowledge(id, Instant::now()).is_some());
+        assert_eq!(keep_alive.acknowledge(id, Instant::now()), None);
+        assert_eq!(keep_alive.latency().millis(), 25);
+    }
+
+    #[tokio::test]
+    async fn times_out_without_a_response() {
+        pause();
+        let mut keep_alive = started();
+
+        advance(TIMING.interval).await;
+        sent_id(&mut keep_alive);
+
+        advance(TIMING.timeout - Duration::from_millis(1)).await;
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::Wait);
+
+        advance(Duration::from_millis(1)).await;
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::TimedOut);
+        assert_eq!(keep_alive.poll(Instant::now()), KeepAliveAction::TimedOut);
+        assert_eq!(keep_alive.latency().millis(), 0);
+    }
+
+    #[tokio::test]
+    async fn smooths_the_latency_shared_with_handles() {
+        pause();
+        let mut keep_alive = started();
+        let shared = keep_alive.latency().clone();
+
+        for round_trip in [200, 200, 200, 40] {
+            advance(TIMING.interval).await;
+            let id = sent_id(&mut keep_alive);
+            advance(Duration::from_millis(round_trip)).await;
+            keep_alive.acknowledge(id, Instant::now());
+        }
+
+        // 50, 87, 115, then a quarter of the way towards 40.
+        assert_eq!(shared.millis(), 96);
+        assert_eq!(shared.get(), Duration::from_millis(96));
+    }
+}
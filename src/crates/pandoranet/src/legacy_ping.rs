@@ -0,0 +1,190 @@
+use std::{io, sync::atomic::Ordering, time::Duration};
+
+use bytes::BytesMut;
+use pandora_utils::config::Config;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    time::timeout,
+};
+
+use crate::registry::enabled_version_names;
+use crate::CONNECTION_MANAGER;
+
+/// First byte of every pre-1.7 server list ping.
+pub const LEGACY_PING_PACKET: u8 = 0xFE;
+/// Packet id of the kick packet the legacy ping is answered with.
+pub const LEGACY_KICK_PACKET: u8 = 0xFF;
+
+/// How long to wait for the byte following `0xFE` before answering in the
+/// pre-1.4 format, in case the client's first write arrived on its own.
+const LEGACY_PING_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Protocol number reported to legacy clients so they always show the server as incompatible.
+const LEGACY_PROTOCOL_VERSION: i32 = 127;
+
+/// Returns true if `data`, the first bytes received on a connection, start a legacy ping.
+pub fn is_legacy_ping(data: &[u8]) -> bool {
+    data.first() == Some(&LEGACY_PING_PACKET)
+}
+
+/// Reads from `reader` until `data` holds the byte that tells the ping formats
+/// apart, or `LEGACY_PING_TIMEOUT` has passed. Clients before 1.4 send nothing
+/// after `0xFE` and are answered once the timeout ends.
+pub async fn read_legacy_ping<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    data: &mut BytesMut,
+) -> io::Result<()> {
+    if data.len() > 1 {
+        return Ok(());
+    }
+
+    match timeout(LEGACY_PING_TIMEOUT, reader.read_buf(data)).await {
+        Ok(read) => read.map(|_| ()),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Builds the kick packet answering a legacy ping.
+///
+/// Clients from 1.4 to 1.6 follow `0xFE` with `0x01` and expect the `§1` response,
+/// older clients send `0xFE` alone and expect `motd§online§max`.
+pub fn legacy_ping_response(data: &[u8]) -> Vec<u8> {
+    let config = Config::get();
+    let online = CONNECTION_MANAGER.connection_count.load(Ordering::Relaxed);
+
+    let response = if data.get(1) == Some(&0x01) {
+        format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            LEGACY_PROTOCOL_VERSION,
+            enabled_version_names(),
+            config.motd,
+            online,
+            config.max_players
+        )
+    } else {
+        format!("{}§{}§{}", config.motd, online, config.max_players)
+    };
+
+    encode_kick(&response)
+}
+
+/// Encodes `message` as a legacy kick packet: the packet id, the length in UTF-16
+/// code units as a big-endian u16, then the UTF-16BE text.
+pub fn encode_kick(message: &str) -> Vec<u8> {
+    let units: Vec<u16> = message.encode_utf16().take(u16::MAX as usize).collect();
+
+    let mut packet = Vec::with_capacity(3 + units.len() * 2);
+    packet.push(LEGACY_KICK_PACKET);
+    packet.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        packet.extend_from_slice(&unit.to_be_bytes());
+    }
+
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{connect, finished, init};
+    use crate::DisconnectReason;
+    use tokio::io::AsyncWriteExt;
+
+    /// `0xFE 0x01` followed by the `MC|PingHost` plugin message of a 1.6.4
+    /// client pinging `localhost:25565`.
+    const PING_1_6: &[u8] = &[
+        0xFE, 0x01, 0xFA, 0x00, 0x0B, 0x00, 0x4D, 0x00, 0x43, 0x00, 0x7C, 0x00, 0x50, 0x00, 0x69,
+        0x00, 0x6E, 0x00, 0x67, 0x00, 0x48, 0x00, 0x6F, 0x00, 0x73, 0x00, 0x74, 0x00, 0x19, 0x4E,
+        0x00, 0x09, 0x00, 0x6C, 0x00, 0x6F, 0x00, 0x63, 0x00, 0x61, 0x00, 0x6C, 0x00, 0x68, 0x00,
+        0x6F, 0x00, 0x73, 0x00, 0x74, 0x00, 0x00, 0x63, 0xDD,
+    ];
+    /// Sent by 1.4 and 1.5 clients.
+    const PING_1_4: &[u8] = &[0xFE, 0x01];
+    /// Sent by clients before 1.4.
+    const PING_BETA: &[u8] = &[0xFE];
+
+    /// Decodes a kick packet back into its message.
+    fn decode_kick(packet: &[u8]) -> String {
+        assert_eq!(packet[0], LEGACY_KICK_PACKET);
+        let length = u16::from_be_bytes([packet[1], packet[2]]) as usize;
+        let units: Vec<u16> = packet[3..]
+            .chunks(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        assert_eq!(units.len(), length);
+        String::from_utf16(&units).unwrap()
+    }
+
+    fn assert_modern_response(packet: &[u8]) {
+        let response = decode_kick(packet);
+        let fields: Vec<&str> = response.split('\0').collect();
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[0], "§1");
+        assert_eq!(fields[1], "127");
+        assert_eq!(fields[2], enabled_version_names());
+        assert_eq!(fields[3], "A Pandora server");
+        assert!(fields[4].parse::<u32>().is_ok());
+        assert_eq!(fields[5], "20");
+    }
+
+    fn assert_beta_response(packet: &[u8]) {
+        let response = decode_kick(packet);
+        let fields: Vec<&str> = response.split('§').collect();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0], "A Pandora server");
+        assert!(fields[1].parse::<u32>().is_ok());
+        assert_eq!(fields[2], "20");
+    }
+
+    #[test]
+    fn encodes_kick_as_utf16() {
+        assert_eq!(
+            encode_kick("§1\0A"),
+            [0xFF, 0x00, 0x04, 0x00, 0xA7, 0x00, 0x31, 0x00, 0x00, 0x00, 0x41]
+        );
+        // Characters outside the BMP take two code units.
+        assert_eq!(
+            encode_kick("😀"),
+            [0xFF, 0x00, 0x02, 0xD8, 0x3D, 0xDE, 0x00]
+        );
+        assert_eq!(encode_kick(""), [0xFF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn answers_captured_pings() {
+        init();
+
+        assert!(is_legacy_ping(PING_1_6));
+        assert!(is_legacy_ping(PING_1_4));
+        assert!(is_legacy_ping(PING_BETA));
+        // A modern handshake starts with its length.
+        assert!(!is_legacy_ping(&[0x10, 0x00, 0xF2, 0x05]));
+
+        assert_modern_response(&legacy_ping_response(PING_1_6));
+        assert_modern_response(&legacy_ping_response(PING_1_4));
+        assert_beta_response(&legacy_ping_response(PING_BETA));
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_rest_of_a_split_ping() {
+        let (mut client, _, task) = connect().await;
+
+        client.stream.write_all(&PING_1_6[..1]).await.unwrap();
+        client.stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.stream.write_all(&PING_1_6[1..]).await.unwrap();
+
+        assert_modern_response(&client.read_to_end().await);
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+
+    #[tokio::test]
+    async fn answers_a_bare_ping_after_the_timeout() {
+        let (mut client, _, task) = connect().await;
+
+        client.stream.write_all(PING_BETA).await.unwrap();
+
+        assert_beta_response(&client.read_to_end().await);
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+}
@@ -1,160 +1,533 @@
-#![feature(box_into_inner)]
-
+use actions::{PlayerAction, PLAYER_ACTIONS};
+use auth::ProfileProperty;
+use bytes::BytesMut;
 use dashmap::DashMap;
+use encryption::{CipherReader, CipherWriter};
+use handler::ServerContext;
+use keep_alive::{KeepAlive, KeepAliveAction, Latency};
 use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
-use packet_manager::PacketManager;
+use packet_manager::{types::varint_types::VarInt, PacketCodec, ProtocolError};
+use pandora_utils::{
+    responses::send_status_response::PlayerSample,
+    types::{
+        decode_types::Decode,
+        encode_types::Encode,
+        packet_types::{Packet, PacketDirection, PacketState},
+    },
+};
 use rand::random;
+use registry::{dispatch_serverbound, encode_packet};
+use serde_json::json;
 use std::{
-    io::Cursor,
+    fmt,
     sync::{
         atomic::{AtomicU32, Ordering},
-        Arc,
+        Arc, RwLock,
     },
+    time::Duration,
+};
+use structs::{
+    login_struct::{disconnect_packet, PendingLogin},
+    protocol::KeepAliveResponse,
 };
-use structs::handshake_struct::HandshakePacket;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-    sync::{mpsc, Mutex},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+    time::{sleep_until, timeout, Instant},
+};
+use tokio_util::{
+    codec::{Decoder, Encoder},
+    sync::CancellationToken,
 };
 use uuid::Uuid;
 
+pub type SocketReader = CipherReader<OwnedReadHalf>;
+pub type SocketWriter = CipherWriter<OwnedWriteHalf>;
+
+/// Number of packets that can be queued before senders wait for the writer.
+const SEND_QUEUE_SIZE: usize = 100;
+/// How long a closing connection waits for its queued packets to be written.
+const WRITER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 lazy_static! {
     pub static ref CONNECTION_MANAGER: ConnectionManager = ConnectionManager::new();
 }
 
+pub mod actions;
+pub mod auth;
+pub mod encryption;
+pub mod handler;
+pub mod keep_alive;
+pub mod legacy_ping;
+pub mod registry;
 pub mod structs;
+#[cfg(test)]
+mod test_util;
 
-#[derive(Debug, Clone)]
+/// A client connection, owned by the task reading from it.
+///
+/// Packets are written by a separate writer task that owns the write half of the
+/// socket and drains the send queue, so sending never waits for a read.
+#[derive(Debug)]
 pub struct Connection {
     pub id: u32,
-    pub socket: Arc<Mutex<TcpStream>>,
+    /// Protocol version used to look up packet ids in the registry.
+    pub protocol_version: i32,
+    pub reader: SocketReader,
+    pub read_buffer: BytesMut,
+    pub read_codec: PacketCodec,
     pub player_uuid: Option<Uuid>,
-    pub send_queue_sender: mpsc::Sender<Vec<u8>>,
-    pub send_queue_receiver: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
+    pub player_name: Option<String>,
+    pub pending_login: Option<PendingLogin>,
+    pub send_queue_sender: mpsc::Sender<Outgoing>,
     pub state: ConnectionState,
+    pub closed: bool,
+    pub shutdown: CancellationToken,
+    /// Started when the connection enters `Play`. Replace it before then to
+    /// use other timings.
+    pub keep_alive: KeepAlive,
+    writer_task: Option<JoinHandle<Result<(), std::io::Error>>>,
 }
 
 impl Connection {
-    pub fn new(
-        socket: TcpStream,
-        id: u32,
-        player_uuid: Option<Uuid>,
-    ) -> (
-        Arc<Self>,
-        mpsc::Sender<Vec<u8>>,
-        Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
-    ) {
-        let (send_queue_sender, send_queue_receiver) = mpsc::channel::<Vec<u8>>(100);
+    /// Splits `socket` and starts the writer task, which runs until the
+    /// connection is closed.
+    pub fn new(socket: TcpStream, id: u32) -> Self {
+        let (read_half, write_half) = socket.into_split();
+        let (send_queue_sender, send_queue_receiver) = mpsc::channel::<Outgoing>(SEND_QUEUE_SIZE);
+        let shutdown = CancellationToken::new();
+
+        let writer_task = tokio::spawn(run_writer(
+            id,
+            CipherWriter::new(write_half),
+            send_queue_receiver,
+            shutdown.clone(),
+        ));
 
-        let connection = Connection {
+        Connection {
             id,
-            socket: Arc::new(Mutex::new(socket)),
-            player_uuid,
+            protocol_version: registry::LATEST_PROTOCOL,
+            reader: CipherReader::new(read_half),
+            read_buffer: BytesMut::with_capacity(4096),
+            read_codec: PacketCodec::new(),
+            player_uuid: None,
+            player_name: None,
+            pending_login: None,
             send_queue_sender,
-            send_queue_receiver: Arc::new(Mutex::new(send_queue_receiver)),
             state: ConnectionState::Unknown,
-        };
-
-        let connection = Arc::new(connection);
+            closed: false,
+            shutdown,
+            keep_alive: KeepAlive::default(),
+            writer_task: Some(writer_task),
+        }
+    }
 
-        (
-            connection.clone(),
-            connection.send_queue_sender.clone(),
-            connection.send_queue_receiver.clone(),
-        )
+    /// What other tasks need to send to this connection or close it.
+    pub fn handle(&self) -> ConnectionHandle {
+        ConnectionHandle {
+            id: self.id,
+            protocol_version: self.protocol_version,
+            state: self.state.clone(),
+            player_uuid: self.player_uuid,
+            send_queue_sender: self.send_queue_sender.clone(),
+            shutdown: self.shutdown.clone(),
+            latency: self.keep_alive.latency().clone(),
+        }
     }
 
-    pub async fn start_connection(&mut self) {
-        self.state = ConnectionState::Handshake;
-        let arc_id = Arc::new(self.id);
+    /// Reads and handles packets until the connection is closed, then waits for
+    /// the packets queued so far to be written. Returns why the connection ended.
+    pub async fn start_connection(&mut self) -> DisconnectReason {
+        self.set_state(ConnectionState::Handshake);
 
-        tokio::spawn(async move {
-            let conn = CONNECTION_MANAGER.connections.get_mut(&*arc_id);
+        let reason = self.start_receiver().await;
 
-            let Some(mut conn) = conn else {
-                error!("Connection not found for id: {}", *arc_id);
-                return;
-            };
+        match self.close_writer().await {
+            Err(e) if !matches!(reason, DisconnectReason::Error(_)) => {
+                DisconnectReason::Error(e.to_string())
+            }
+            _ => reason,
+        }
+    }
 
-            let res = conn.start_sender().await;
+    /// Stops the writer task once it has written everything queued before this
+    /// call, or after `WRITER_SHUTDOWN_TIMEOUT` if the client stops reading.
+    /// Returns the error that stopped the writer early, if any.
+    async fn close_writer(&mut self) -> Result<(), std::io::Error> {
+        let Some(writer_task) = self.writer_task.take() else {
+            return Ok(());
+        };
+        let abort_handle = writer_task.abort_handle();
 
-            if let Err(e) = res {
-                error!("Error in sender: {:?}", e);
-            }
-        });
+        let flushed = async {
+            self.send(Outgoing::Close).await;
+            writer_task.await.map_err(std::io::Error::other)?
+        };
 
-        if let Err(e) = Connection::start_receiver(self, self.socket.clone()).await {
-            error!("Receiver task failed with error: {}", e);
+        match timeout(WRITER_SHUTDOWN_TIMEOUT, flushed).await {
+            Ok(result) => result,
+            Err(_) => {
+                debug!(
+                    "Connection {} did not write its queued packets in time",
+                    self.id
+                );
+                abort_handle.abort();
+                Ok(())
+            }
         }
     }
 
-    async fn start_sender(&mut self) -> Result<(), std::io::Error> {
+    async fn start_receiver(&mut self) -> DisconnectReason {
+        let mut first_read = true;
+
         loop {
-            match self.send_queue_receiver.lock().await.recv().await {
-                Some(packet) => {
-                    if let Err(e) = self.socket.lock().await.write_all(&packet).await {
-                        error!("Failed to write to socket: {:?}", e);
-                        break;
+            let frame = match self.read_codec.decode(&mut self.read_buffer) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => match {
+                    let keep_alive = self.keep_alive.deadline();
+                    tokio::select! {
+                        read = self.reader.read_buf(&mut self.read_buffer) => read,
+                        _ = self.shutdown.cancelled() => {
+                            debug!("Connection {} was shut down", self.id);
+                            return DisconnectReason::Shutdown;
+                        }
+                        _ = sleep_until(keep_alive.unwrap_or_else(Instant::now)),
+                            if keep_alive.is_some() =>
+                        {
+                            if let Err(reason) = self.poll_keep_alive().await {
+                                return reason;
+                            }
+                            continue;
+                        }
+                    }
+                } {
+                    Ok(0) => {
+                        debug!("Connection {} closed by peer", self.id);
+                        return DisconnectReason::ClosedByPeer;
                     }
+                    Ok(_) if first_read && legacy_ping::is_legacy_ping(&self.read_buffer) => {
+                        debug!("Legacy server list ping from connection {}", self.id);
+                        if let Err(e) =
+                            legacy_ping::read_legacy_ping(&mut self.reader, &mut self.read_buffer)
+                                .await
+                        {
+                            debug!("Failed to read legacy ping from {}: {}", self.id, e);
+                            return DisconnectReason::Error(e.to_string());
+                        }
+                        let response = legacy_ping::legacy_ping_response(&self.read_buffer);
+                        self.send(Outgoing::Raw(response)).await;
+                        return DisconnectReason::Closed;
+                    }
+                    Ok(_) => {
+                        first_read = false;
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Failed to read from connection {}: {}", self.id, e);
+                        return DisconnectReason::Error(e.to_string());
+                    }
+                },
+                Err(e) => {
+                    warn!("Disconnecting {}: malformed frame: {}", self.id, e);
+                    return DisconnectReason::Error(e.to_string());
                 }
-                None => {}
+            };
+
+            let packet_length = frame.len();
+            let mut data = &frame[..];
+
+            let result = match VarInt::decode(&mut data) {
+                Ok(packet_id) => {
+                    self.handle_packet(packet_id.to_i32(), packet_length, &mut data)
+                        .await
+                }
+                Err(e) => Err(e.in_field("packet id")),
+            };
+
+            if let Err(e) = result {
+                warn!("Disconnecting {}: {}", self.id, e);
+                self.disconnect_on_error(&e).await;
+                return DisconnectReason::Error(e.to_string());
+            }
+
+            if self.closed {
+                debug!("Closing connection {}", self.id);
+                return DisconnectReason::Closed;
             }
         }
-        Ok(())
     }
 
-    async fn start_receiver(
+    async fn handle_packet(
         &mut self,
-        socket: Arc<Mutex<TcpStream>>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let mut socket = socket.lock().await;
+        packet_id: i32,
+        packet_length: usize,
+        data: &mut &[u8],
+    ) -> Result<(), ProtocolError> {
+        let Some(state) = self.state.packet_state() else {
+            return Err(ProtocolError::BadState {
+                packet_id,
+                state: format!("{:?}", self.state),
+            });
+        };
 
-        loop {
-            let mut length_buffer = [0u8; 1];
-            if socket.read_exact(&mut length_buffer).await.is_err() {
-                error!("Failed to read packet length.");
-                break;
+        let server = ServerContext::current();
+        if dispatch_serverbound(self.protocol_version, state, packet_id, data, self, &server)
+            .await?
+        {
+            return Ok(());
+        }
+
+        let name = registry::packet_name(
+            self.protocol_version,
+            state,
+            PacketDirection::Serverbound,
+            packet_id,
+        );
+        warn!(
+            "Unhandled packet id {:#04x} ({}) in state {:?} with length {}",
+            packet_id,
+            name.unwrap_or("unknown"),
+            self.state,
+            packet_length
+        );
+
+        if server.config.disconnect_on_unknown_packet {
+            return Err(ProtocolError::UnknownPacket {
+                packet_id,
+                state: format!("{:?}", self.state),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Sends a Keep Alive when one is due. Fails with `TimedOut` after sending a
+    /// disconnect once the client left the outstanding one unanswered for too long.
+    async fn poll_keep_alive(&mut self) -> Result<(), DisconnectReason> {
+        match self.keep_alive.poll(Instant::now()) {
+            KeepAliveAction::Send(id) => {
+                let data = encode_packet(self.protocol_version, &KeepAliveResponse { id })
+                    .map_err(|err| {
+                        error!("Failed to encode keep alive {:?}", err);
+                        DisconnectReason::Error(err.to_string())
+                    })?;
+                self.push_to_queue(data).await;
+                Ok(())
             }
+            KeepAliveAction::TimedOut => {
+                info!("Connection {} timed out", self.id);
 
-            let length = length_buffer[0] as usize;
-            let mut buffer = vec![0u8; length];
+                let reason = json!({ "translate": "disconnect.timeout" });
+                match disconnect_packet(self.protocol_version, &self.state, &reason) {
+                    Ok(Some(data)) => self.push_to_queue(data).await,
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to disconnect {}: {}", self.id, e),
+                }
 
-            if socket.read_exact(&mut buffer).await.is_err() {
-                error!("Failed to read packet data.");
-                break;
+                Err(DisconnectReason::TimedOut)
             }
+            KeepAliveAction::Wait => Ok(()),
+        }
+    }
+
+    /// Moves the connection to `state`, updating its entry in `CONNECTION_MANAGER`
+    /// with the current protocol version and player, then runs the state change
+    /// hooks. Keep Alive starts once the connection enters `Play`.
+    pub fn set_state(&mut self, state: ConnectionState) {
+        debug!(
+            "Connection {} state {:?} -> {:?}",
+            self.id, self.state, state
+        );
+        if state == ConnectionState::Play {
+            self.keep_alive.start(Instant::now());
+        }
+        let previous = std::mem::replace(&mut self.state, state);
+        CONNECTION_MANAGER.update_connection(self.handle(), &previous);
+    }
+
+    /// Encrypts the connection in both directions, including any bytes that were
+    /// already received after the client switched to encryption.
+    pub async fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<(), ProtocolError> {
+        self.reader
+            .enable_encryption(shared_secret, &mut self.read_buffer)?;
+        self.send(Outgoing::EnableEncryption(shared_secret.to_vec()))
+            .await;
+
+        debug!("Enabled encryption for connection {}", self.id);
+        Ok(())
+    }
+
+    /// Compresses packets of at least `threshold` bytes in both directions. Packets
+    /// queued before this call are still sent uncompressed.
+    pub async fn enable_compression(&mut self, threshold: i32) {
+        self.read_codec.set_compression(threshold);
+        self.send(Outgoing::SetCompression(threshold)).await;
+
+        debug!(
+            "Set compression threshold of connection {} to {}",
+            self.id, threshold
+        );
+    }
+
+    /// Tells the client why its connection is being closed after a protocol
+    /// error. Clients only show a reason during login and play.
+    async fn disconnect_on_error(&mut self, error: &ProtocolError) {
+        let Some(reason) = error.disconnect_reason() else {
+            return;
+        };
 
-            let mut cursor = Cursor::new([length_buffer.to_vec(), buffer].concat());
+        let reason = json!({ "text": reason });
+        match disconnect_packet(self.protocol_version, &self.state, &reason) {
+            Ok(Some(packet)) => self.push_to_queue(packet).await,
+            Ok(None) => {}
+            Err(e) => error!("Failed to encode disconnect for {}: {}", self.id, e),
+        }
+    }
 
-            let packet_length = PacketManager::read_var_int(&mut cursor).await?;
-            let packet_id = PacketManager::read_var_int(&mut cursor).await?;
+    /// Stops reading from the connection once the current packet has been handled.
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
 
-            match packet_id.to_i32() {
-                0x00 => {
-                    let handshake_packet = HandshakePacket::decode(&mut cursor).await.unwrap();
-                    debug!("{}", handshake_packet);
-                    handshake_packet.handle(self).await?;
+    pub async fn push_to_queue(&self, packet: Vec<u8>) {
+        self.send(Outgoing::Packet(packet)).await;
+    }
+
+    async fn send(&self, outgoing: Outgoing) {
+        if let Err(e) = self.send_queue_sender.send(outgoing).await {
+            error!("Failed to send packet to queue: {:?}", e);
+        }
+    }
+}
+
+/// Writes everything queued for a connection to its socket, in order, until
+/// `Outgoing::Close` is received. A write error shuts the connection down.
+async fn run_writer(
+    id: u32,
+    mut writer: SocketWriter,
+    mut queue: mpsc::Receiver<Outgoing>,
+    shutdown: CancellationToken,
+) -> Result<(), std::io::Error> {
+    let mut codec = PacketCodec::new();
+    let mut frame = BytesMut::new();
+
+    let result: Result<(), std::io::Error> = async {
+        while let Some(outgoing) = queue.recv().await {
+            match outgoing {
+                Outgoing::Packet(packet) => {
+                    frame.clear();
+                    codec.encode(&packet[..], &mut frame)?;
+                    writer.write_all(&frame).await?;
                 }
-                _ => {
-                    warn!(
-                        "Unknown packet id {} with length {}",
-                        packet_id, packet_length
-                    );
+                Outgoing::Batch(packets) => {
+                    frame.clear();
+                    for packet in packets {
+                        codec.encode(&packet[..], &mut frame)?;
+                    }
+                    writer.write_all(&frame).await?;
+                }
+                Outgoing::Raw(data) => writer.write_all(&data).await?,
+                Outgoing::SetCompression(threshold) => {
+                    codec.set_compression(threshold);
+                    continue;
                 }
+                Outgoing::EnableEncryption(shared_secret) => {
+                    writer.enable_encryption(&shared_secret)?;
+                    continue;
+                }
+                Outgoing::Close => break,
             }
+
+            writer.flush().await?;
         }
-        Ok(())
+
+        writer.shutdown().await
     }
+    .await;
 
-    pub async fn push_to_queue(&self, packet: Vec<u8>) {
-        if let Err(e) = self.send_queue_sender.send(packet).await {
+    if let Err(e) = &result {
+        debug!("Failed to write to connection {}: {}", id, e);
+        shutdown.cancel();
+    }
+
+    result
+}
+
+/// Items written by the writer task, in the order they were queued.
+#[derive(Debug)]
+pub enum Outgoing {
+    /// A packet id followed by its data, framed and compressed when written.
+    Packet(Vec<u8>),
+    /// Packets written together and flushed once, queued by the game loop at
+    /// the end of a tick.
+    Batch(Vec<Vec<u8>>),
+    /// Bytes written to the socket as they are, used for the legacy ping response.
+    Raw(Vec<u8>),
+    /// Compress packets of at least this many bytes from here on.
+    SetCompression(i32),
+    /// Encrypt everything written from here on with this shared secret.
+    EnableEncryption(Vec<u8>),
+    /// Shut the socket down once everything queued before has been written.
+    Close,
+}
+
+/// The parts of a connection that other tasks use to reach it. `protocol_version`,
+/// `state` and `player_uuid` are copies, updated on every state change.
+#[derive(Debug, Clone)]
+pub struct ConnectionHandle {
+    pub id: u32,
+    pub protocol_version: i32,
+    pub state: ConnectionState,
+    pub player_uuid: Option<Uuid>,
+    pub send_queue_sender: mpsc::Sender<Outgoing>,
+    pub shutdown: CancellationToken,
+    /// Measured by Keep Alive, zero until the first response.
+    pub latency: Latency,
+}
+
+impl ConnectionHandle {
+    /// Queues a packet id followed by its data.
+    pub async fn send_packet(&self, packet: Vec<u8>) {
+        if let Err(e) = self.send_queue_sender.send(Outgoing::Packet(packet)).await {
             error!("Failed to send packet to queue: {:?}", e);
         }
     }
 }
 
+/// Why a connection ended, passed to the disconnect hooks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client closed the socket.
+    ClosedByPeer,
+    /// The server closed the connection after answering a ping or sending a
+    /// disconnect packet.
+    Closed,
+    /// The connection was shut down by another task, e.g. a kick or server shutdown.
+    Shutdown,
+    /// The client did not answer a Keep Alive in time.
+    TimedOut,
+    /// Reading, handling or writing a packet failed.
+    Error(String),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::ClosedByPeer => write!(f, "closed by peer"),
+            DisconnectReason::Closed => write!(f, "closed"),
+            DisconnectReason::Shutdown => write!(f, "shut down"),
+            DisconnectReason::TimedOut => write!(f, "timed out"),
+            DisconnectReason::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum ConnectionState {
     Unknown,
@@ -164,9 +537,102 @@ pub enum ConnectionState {
     Play,
 }
 
+impl ConnectionState {
+    /// The registry state whose packets are expected in this state.
+    pub fn packet_state(&self) -> Option<PacketState> {
+        match self {
+            ConnectionState::Unknown => None,
+            ConnectionState::Handshake => Some(PacketState::Handshake),
+            ConnectionState::Status => Some(PacketState::Status),
+            ConnectionState::Login => Some(PacketState::Login),
+            ConnectionState::Play => Some(PacketState::Play),
+        }
+    }
+}
+
+/// A player that has completed login, reachable from other connections.
+#[derive(Debug, Clone)]
+pub struct OnlinePlayer {
+    pub name: String,
+    pub connection_id: u32,
+    pub protocol_version: i32,
+    pub send_queue_sender: mpsc::Sender<Outgoing>,
+    pub shutdown: CancellationToken,
+    /// Round trip time shown in the tab list.
+    pub latency: Latency,
+    /// Skin and cape from the session server, none in offline mode.
+    pub properties: Arc<[ProfileProperty]>,
+}
+
+impl OnlinePlayer {
+    /// Sends a play-state disconnect with `reason` and stops the player's connection,
+    /// without waiting. The disconnect is left out when the send queue is full, so a
+    /// client that stopped reading cannot hold up the caller.
+    pub fn kick(&self, reason: &str) {
+        let reason = json!({ "text": reason });
+        match disconnect_packet(self.protocol_version, &ConnectionState::Play, &reason) {
+            Ok(Some(data)) => match self.send_queue_sender.try_send(Outgoing::Packet(data)) {
+                Ok(()) | Err(TrySendError::Closed(_)) => {}
+                Err(TrySendError::Full(_)) => {
+                    debug!(
+                        "Send queue of {} is full, closing without a reason",
+                        self.name
+                    )
+                }
+            },
+            Ok(None) => {}
+            Err(e) => error!("Failed to kick {}: {}", self.name, e),
+        }
+
+        self.shutdown.cancel();
+    }
+
+    /// Queues `packets` to be written together, without waiting. A player whose
+    /// send queue is full is not keeping up and is disconnected.
+    pub fn send_batch(&self, packets: Vec<Vec<u8>>) {
+        match self.send_queue_sender.try_send(Outgoing::Batch(packets)) {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!("Disconnecting {}: send queue is full", self.name);
+                self.shutdown.cancel();
+            }
+        }
+    }
+}
+
+type ConnectHook = Box<dyn Fn(&ConnectionHandle) + Send + Sync>;
+type StateChangeHook = Box<dyn Fn(&ConnectionHandle, &ConnectionState) + Send + Sync>;
+type DisconnectHook = Box<dyn Fn(&ConnectionHandle, &DisconnectReason) + Send + Sync>;
+
+#[derive(Default)]
+struct ConnectionHooks {
+    connect: Vec<ConnectHook>,
+    state_change: Vec<StateChangeHook>,
+    disconnect: Vec<DisconnectHook>,
+}
+
+/// Every open connection and online player. Connections are added when accepted
+/// and removed when their tasks end.
+///
+/// Other subsystems can follow connections with hooks, which run on the
+/// connection's task and must not block or register further hooks:
+///
+/// ```ignore
+/// CONNECTION_MANAGER.on_disconnect(|conn, reason| {
+///     info!("Connection {} ended: {}", conn.id, reason);
+/// });
+/// ```
 pub struct ConnectionManager {
-    pub connections: DashMap<u32, Connection>,
+    pub connections: DashMap<u32, ConnectionHandle>,
     pub connection_count: AtomicU32,
+    pub players: DashMap<Uuid, OnlinePlayer>,
+    hooks: RwLock<ConnectionHooks>,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ConnectionManager {
@@ -174,6 +640,8 @@ impl ConnectionManager {
         Self {
             connections: DashMap::new(),
             connection_count: AtomicU32::new(0),
+            players: DashMap::new(),
+            hooks: RwLock::new(ConnectionHooks::default()),
         }
     }
 
@@ -186,31 +654,619 @@ impl ConnectionManager {
         }
     }
 
-    pub fn add_connection(&self, connection: Connection) {
+    /// Runs `hook` for every connection added from now on.
+    pub fn on_connect(&self, hook: impl Fn(&ConnectionHandle) + Send + Sync + 'static) {
+        self.hooks.write().unwrap().connect.push(Box::new(hook));
+    }
+
+    /// Runs `hook` with the updated connection and its previous state whenever a
+    /// connection changes state.
+    pub fn on_state_change(
+        &self,
+        hook: impl Fn(&ConnectionHandle, &ConnectionState) + Send + Sync + 'static,
+    ) {
+        self.hooks
+            .write()
+            .unwrap()
+            .state_change
+            .push(Box::new(hook));
+    }
+
+    /// Runs `hook` for every connection removed from now on, after its player has
+    /// been removed.
+    pub fn on_disconnect(
+        &self,
+        hook: impl Fn(&ConnectionHandle, &DisconnectReason) + Send + Sync + 'static,
+    ) {
+        self.hooks.write().unwrap().disconnect.push(Box::new(hook));
+    }
+
+    pub fn add_connection(&self, connection: ConnectionHandle) {
         self.connection_count.fetch_add(1, Ordering::Relaxed);
-        self.connections.insert(connection.id, connection);
+        self.connections.insert(connection.id, connection.clone());
+
+        for hook in &self.hooks.read().unwrap().connect {
+            hook(&connection);
+        }
     }
 
-    pub fn remove_connection(&self, id: u32) {
-        if self.connections.remove(&id).is_some() {
-            self.connection_count.fetch_sub(1, Ordering::Relaxed);
+    /// Replaces the entry of a connection that changed from `previous` to
+    /// `connection.state`. Connections that were never added are ignored.
+    pub fn update_connection(&self, connection: ConnectionHandle, previous: &ConnectionState) {
+        match self.connections.get_mut(&connection.id) {
+            Some(mut entry) => *entry = connection.clone(),
+            None => return,
+        }
+
+        for hook in &self.hooks.read().unwrap().state_change {
+            hook(&connection, previous);
         }
     }
+
+    /// Removes a connection and its player, telling the game loop the player
+    /// left. Called when the connection's tasks have ended.
+    pub fn remove_connection(&self, id: u32, reason: &DisconnectReason) {
+        let Some((_, connection)) = self.connections.remove(&id) else {
+            return;
+        };
+        self.connection_count.fetch_sub(1, Ordering::Relaxed);
+
+        if let Some(uuid) = connection.player_uuid {
+            if self
+                .players
+                .remove_if(&uuid, |_, player| player.connection_id == id)
+                .is_some()
+            {
+                PLAYER_ACTIONS.send(PlayerAction::Left {
+                    uuid,
+                    connection_id: id,
+                });
+            }
+        }
+
+        for hook in &self.hooks.read().unwrap().disconnect {
+            hook(&connection, reason);
+        }
+    }
+
+    pub fn connection(&self, id: u32) -> Option<ConnectionHandle> {
+        self.connections.get(&id).map(|entry| entry.value().clone())
+    }
+
+    /// Returns every connection currently in `state`.
+    pub fn connections_in(&self, state: &ConnectionState) -> Vec<ConnectionHandle> {
+        self.connections
+            .iter()
+            .filter(|entry| entry.value().state == *state)
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    pub fn add_player(&self, uuid: Uuid, player: OnlinePlayer) {
+        self.players.insert(uuid, player);
+    }
+
+    pub fn player(&self, uuid: Uuid) -> Option<OnlinePlayer> {
+        self.players.get(&uuid).map(|entry| entry.value().clone())
+    }
+
+    /// Finds an online player by name, ignoring case.
+    pub fn player_by_name(&self, name: &str) -> Option<OnlinePlayer> {
+        self.players
+            .iter()
+            .find(|entry| entry.value().name.eq_ignore_ascii_case(name))
+            .map(|entry| entry.value().clone())
+    }
+
+    /// Finds an online player by UUID or, ignoring case, by name.
+    pub fn find_player(&self, uuid: Uuid, name: &str) -> Option<OnlinePlayer> {
+        self.player(uuid).or_else(|| self.player_by_name(name))
+    }
+
+    /// Queues `packet` on every connection in `state`, encoded once for each
+    /// protocol version in use. Returns the number of connections it was sent to.
+    pub async fn broadcast<P: Packet + Encode>(
+        &self,
+        state: &ConnectionState,
+        packet: &P,
+    ) -> Result<usize, ProtocolError> {
+        let connections = self.connections_in(state);
+        let mut encoded: Vec<(i32, Vec<u8>)> = Vec::new();
+
+        for connection in &connections {
+            let data = match encoded
+                .iter()
+                .find(|(protocol, _)| *protocol == connection.protocol_version)
+            {
+                Some((_, data)) => data.clone(),
+                None => {
+                    let data = encode_packet(connection.protocol_version, packet)?;
+                    encoded.push((connection.protocol_version, data.clone()));
+                    data
+                }
+            };
+            connection.send_packet(data).await;
+        }
+
+        Ok(connections.len())
+    }
+
+    /// Queues `packet` on the connection of every online player.
+    pub async fn broadcast_to_players<P: Packet + Encode>(
+        &self,
+        packet: &P,
+    ) -> Result<usize, ProtocolError> {
+        self.broadcast(&ConnectionState::Play, packet).await
+    }
+
+    /// Sends a disconnect with `reason` to every player and closes every
+    /// connection. Each connection still writes what it had queued. Never
+    /// waits: a player whose queue is full is closed without the disconnect.
+    pub fn close_all(&self, reason: &str) {
+        // Collected first so no map guard is held while kicking.
+        let players: Vec<OnlinePlayer> = self
+            .players
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        for player in players {
+            player.kick(reason);
+        }
+
+        for entry in self.connections.iter() {
+            entry.value().shutdown.cancel();
+        }
+    }
+
+    /// Returns up to `limit` online players for the server list hover sample.
+    pub fn player_sample(&self, limit: usize) -> Vec<PlayerSample> {
+        self.players
+            .iter()
+            .take(limit)
+            .map(|entry| PlayerSample {
+                name: entry.value().name.clone(),
+                id: entry.key().hyphenated().to_string(),
+                latency: entry.value().latency.millis(),
+            })
+            .collect()
+    }
 }
 
-pub async fn handle_connection(socket: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
-    let id = CONNECTION_MANAGER.generate_id();
-    let (connection, _send_queue_sender, _send_queue_receiver) = Connection::new(socket, id, None);
+/// Removes a connection from `CONNECTION_MANAGER` when dropped, so it is also
+/// removed when a packet handler panics.
+struct Registration {
+    id: u32,
+    reason: DisconnectReason,
+}
 
-    CONNECTION_MANAGER.add_connection((*connection).clone());
+impl Drop for Registration {
+    fn drop(&mut self) {
+        CONNECTION_MANAGER.remove_connection(self.id, &self.reason);
+    }
+}
 
-    let mut connection_ref = CONNECTION_MANAGER
-        .connections
-        .get_mut(&id)
-        .ok_or("Connection not found")?;
+/// Serves a client until its connection is closed.
+pub async fn handle_connection(
+    socket: TcpStream,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let id = CONNECTION_MANAGER.generate_id();
+    let mut connection = Connection::new(socket, id);
 
-    connection_ref.start_connection().await;
+    CONNECTION_MANAGER.add_connection(connection.handle());
+    let mut registration = Registration {
+        id,
+        reason: DisconnectReason::Error("connection task stopped".to_string()),
+    };
     info!("Established connection with the id {}", id);
 
+    registration.reason = connection.start_connection().await;
+    debug!("Connection {} ended: {}", id, registration.reason);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use nbt::Value;
+    use pandora_utils::types::decode_types::Decode;
+
+    use super::*;
+    use crate::structs::login_struct::offline_uuid;
+    use crate::structs::protocol::{JoinGameResponse, KeepAliveResponse};
+    use crate::test_util::{connect, finished, lock_config, PROTOCOL};
+
+    #[tokio::test]
+    async fn answers_status_and_ping() {
+        let (mut client, _, task) = connect().await;
+
+        client.handshake(1).await;
+        client.send(0x00, &[]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x00);
+        let response = String::decode(&mut &data[..]).unwrap();
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["version"]["protocol"], PROTOCOL);
+        assert_eq!(response["players"]["max"], 20);
+
+        client.send(0x01, &[&0x0123_4567_89AB_CDEFi64]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x01);
+        assert_eq!(i64::decode(&mut &data[..]).unwrap(), 0x0123_4567_89AB_CDEF);
+
+        assert!(client.read_to_end().await.is_empty());
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+
+    #[tokio::test]
+    async fn logs_in_offline_and_joins_the_game() {
+        let _config = lock_config().await;
+        let (mut client, handle, task) = connect().await;
+
+        client.handshake(2).await;
+        client.send(0x00, &[&"Tester".to_string()]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x02);
+        let mut data = &data[..];
+        assert_eq!(Uuid::decode(&mut data).unwrap(), offline_uuid("Tester"));
+        assert_eq!(String::decode(&mut data).unwrap(), "Tester");
+        assert!(data.is_empty());
+
+        let (packet_id, _) = client.receive().await;
+        assert_eq!(packet_id, 0x24);
+
+        let connection = CONNECTION_MANAGER.connection(handle.id).unwrap();
+        assert_eq!(connection.state, ConnectionState::Play);
+        assert_eq!(connection.player_uuid, Some(offline_uuid("Tester")));
+
+        drop(client);
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+        assert!(CONNECTION_MANAGER.player(offline_uuid("Tester")).is_none());
+    }
+
+    #[tokio::test]
+    async fn logs_in_and_joins_the_game_on_1_17_1() {
+        let _config = lock_config().await;
+        let (mut client, handle, task) = connect().await;
+
+        client.handshake_with(756, 2).await;
+        client.send(0x00, &[&"Newer".to_string()]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x02);
+        let mut data = &data[..];
+        assert_eq!(Uuid::decode(&mut data).unwrap(), offline_uuid("Newer"));
+        assert_eq!(String::decode(&mut data).unwrap(), "Newer");
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x26);
+        let mut data = &data[..];
+        let join_game = JoinGameResponse::decode(&mut data).unwrap();
+        assert!(data.is_empty());
+        let Value::Compound(dimension) =
+            Value::decode(&mut join_game.dimension.as_bytes()).unwrap()
+        else {
+            panic!("dimension is not a compound");
+        };
+        assert_eq!(dimension.get("min_y"), Some(&Value::Int(0)));
+        assert_eq!(dimension.get("height"), Some(&Value::Int(256)));
+
+        let connection = CONNECTION_MANAGER.connection(handle.id).unwrap();
+        assert_eq!(connection.state, ConnectionState::Play);
+        assert_eq!(connection.protocol_version, 756);
+
+        drop(client);
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+    }
+
+    #[tokio::test]
+    async fn writes_queued_packets_while_the_reader_waits() {
+        let (mut client, handle, task) = connect().await;
+
+        // The client sends nothing, so the connection is waiting for a handshake.
+        handle.send_packet(vec![0x7f, 1, 2, 3]).await;
+        handle
+            .send_queue_sender
+            .send(Outgoing::Batch(vec![vec![0x7e], vec![0x7d, 4]]))
+            .await
+            .unwrap();
+
+        assert_eq!(client.receive().await, (0x7f, vec![1, 2, 3]));
+        assert_eq!(client.receive().await, (0x7e, vec![]));
+        assert_eq!(client.receive().await, (0x7d, vec![4]));
+        assert!(!task.is_finished());
+
+        drop(client);
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+    }
+
+    #[test]
+    fn kicks_without_waiting_on_a_full_queue() {
+        let (send_queue_sender, mut queue) = mpsc::channel(1);
+        let player = OnlinePlayer {
+            name: "Tester".to_string(),
+            connection_id: 1,
+            protocol_version: PROTOCOL,
+            send_queue_sender,
+            shutdown: CancellationToken::new(),
+            latency: Latency::default(),
+            properties: Arc::from([]),
+        };
+
+        player.kick("first");
+        assert!(player.shutdown.is_cancelled());
+        assert!(matches!(queue.try_recv(), Ok(Outgoing::Packet(_))));
+
+        player.send_queue_sender.try_send(Outgoing::Close).unwrap();
+        player.kick("second");
+        assert!(matches!(queue.try_recv(), Ok(Outgoing::Close)));
+        assert!(queue.try_recv().is_err());
+    }
+
+    #[test]
+    fn closes_everyone_without_waiting_on_full_queues() {
+        let manager = ConnectionManager::new();
+        let (send_queue_sender, mut queue) = mpsc::channel(1);
+        send_queue_sender.try_send(Outgoing::Close).unwrap();
+        let shutdown = CancellationToken::new();
+
+        manager.connections.insert(
+            1,
+            ConnectionHandle {
+                id: 1,
+                protocol_version: PROTOCOL,
+                state: ConnectionState::Play,
+                player_uuid: Some(offline_uuid("Tester")),
+                send_queue_sender: send_queue_sender.clone(),
+                shutdown: shutdown.clone(),
+                latency: Latency::default(),
+            },
+        );
+        manager.players.insert(
+            offline_uuid("Tester"),
+            OnlinePlayer {
+                name: "Tester".to_string(),
+                connection_id: 1,
+                protocol_version: PROTOCOL,
+                send_queue_sender,
+                shutdown: shutdown.clone(),
+                latency: Latency::default(),
+                properties: Arc::from([]),
+            },
+        );
+        let status = CancellationToken::new();
+        let (status_sender, _) = mpsc::channel(1);
+        manager.connections.insert(
+            2,
+            ConnectionHandle {
+                id: 2,
+                protocol_version: PROTOCOL,
+                state: ConnectionState::Status,
+                player_uuid: None,
+                send_queue_sender: status_sender,
+                shutdown: status.clone(),
+                latency: Latency::default(),
+            },
+        );
+
+        manager.close_all("Server closed");
+
+        assert!(shutdown.is_cancelled());
+        assert!(status.is_cancelled());
+        assert!(matches!(queue.try_recv(), Ok(Outgoing::Close)));
+        assert!(queue.try_recv().is_err());
+    }
+
+    fn test_handle(
+        id: u32,
+        state: ConnectionState,
+        protocol_version: i32,
+    ) -> (ConnectionHandle, mpsc::Receiver<Outgoing>) {
+        let (send_queue_sender, queue) = mpsc::channel(8);
+        let handle = ConnectionHandle {
+            id,
+            protocol_version,
+            state,
+            player_uuid: None,
+            send_queue_sender,
+            shutdown: CancellationToken::new(),
+            latency: Latency::default(),
+        };
+        (handle, queue)
+    }
+
+    fn test_player(name: &str, handle: &ConnectionHandle) -> OnlinePlayer {
+        OnlinePlayer {
+            name: name.to_string(),
+            connection_id: handle.id,
+            protocol_version: handle.protocol_version,
+            send_queue_sender: handle.send_queue_sender.clone(),
+            shutdown: handle.shutdown.clone(),
+            latency: handle.latency.clone(),
+            properties: Arc::from([]),
+        }
+    }
+
+    /// Records every hook call of `manager` as a line of text.
+    fn record_hooks(manager: &ConnectionManager) -> Arc<std::sync::Mutex<Vec<String>>> {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let log = events.clone();
+        manager.on_connect(move |conn| log.lock().unwrap().push(format!("connect {}", conn.id)));
+        let log = events.clone();
+        manager.on_state_change(move |conn, previous| {
+            log.lock().unwrap().push(format!(
+                "state {} {:?} -> {:?}",
+                conn.id, previous, conn.state
+            ))
+        });
+        let log = events.clone();
+        manager.on_disconnect(move |conn, reason| {
+            log.lock()
+                .unwrap()
+                .push(format!("disconnect {} {}", conn.id, reason))
+        });
+
+        events
+    }
+
+    #[test]
+    fn runs_hooks_once_per_connection() {
+        let manager = ConnectionManager::new();
+        let events = record_hooks(&manager);
+
+        // A client that leaves during login, before it has a player.
+        let (mut early, _queue) = test_handle(1, ConnectionState::Handshake, PROTOCOL);
+        manager.add_connection(early.clone());
+        early.state = ConnectionState::Login;
+        manager.update_connection(early.clone(), &ConnectionState::Handshake);
+        manager.remove_connection(1, &DisconnectReason::ClosedByPeer);
+        manager.remove_connection(1, &DisconnectReason::Shutdown);
+        manager.update_connection(early, &ConnectionState::Login);
+
+        // A player in the world.
+        let (mut player, _queue) = test_handle(2, ConnectionState::Play, PROTOCOL);
+        player.player_uuid = Some(offline_uuid("Tester"));
+        manager.add_connection(player.clone());
+        manager.add_player(offline_uuid("Tester"), test_player("Tester", &player));
+        manager.remove_connection(2, &DisconnectReason::TimedOut);
+        manager.remove_connection(2, &DisconnectReason::TimedOut);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "connect 1",
+                "state 1 Handshake -> Login",
+                "disconnect 1 closed by peer",
+                "connect 2",
+                "disconnect 2 timed out",
+            ]
+        );
+        assert_eq!(manager.connection_count.load(Ordering::Relaxed), 0);
+        assert!(manager.connections.is_empty());
+        assert!(manager.players.is_empty());
+    }
+
+    #[tokio::test]
+    async fn runs_the_disconnect_hook_for_clients_leaving_during_login() {
+        let disconnects = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = disconnects.clone();
+        CONNECTION_MANAGER.on_disconnect(move |conn, reason| {
+            log.lock()
+                .unwrap()
+                .push((conn.id, conn.state.clone(), reason.clone()))
+        });
+
+        let (mut client, handle, task) = connect().await;
+        client.handshake(2).await;
+        // Leaves after the handshake, before sending Login Start.
+        client.stream.shutdown().await.unwrap();
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+
+        let ours: Vec<_> = disconnects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _, _)| *id == handle.id)
+            .cloned()
+            .collect();
+        assert_eq!(
+            ours,
+            [(
+                handle.id,
+                ConnectionState::Login,
+                DisconnectReason::ClosedByPeer
+            )]
+        );
+        assert!(CONNECTION_MANAGER.connection(handle.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn finds_logged_in_players_by_name_and_uuid() {
+        let _config = lock_config().await;
+        let (mut client, handle, task) = connect().await;
+        let uuid = offline_uuid("Lookup");
+
+        client.handshake(2).await;
+        client.send(0x00, &[&"Lookup".to_string()]).await;
+        assert_eq!(client.receive().await.0, 0x02);
+        assert_eq!(client.receive().await.0, 0x24);
+
+        let player = CONNECTION_MANAGER.player(uuid).unwrap();
+        assert_eq!(player.name, "Lookup");
+        assert_eq!(player.connection_id, handle.id);
+        let by_name = CONNECTION_MANAGER.player_by_name("lOOKUP").unwrap();
+        assert_eq!(by_name.connection_id, handle.id);
+        let found = CONNECTION_MANAGER
+            .find_player(Uuid::nil(), "lookup")
+            .unwrap();
+        assert_eq!(found.connection_id, handle.id);
+        let found = CONNECTION_MANAGER.find_player(uuid, "Someone").unwrap();
+        assert_eq!(found.connection_id, handle.id);
+        assert!(CONNECTION_MANAGER
+            .connections_in(&ConnectionState::Play)
+            .iter()
+            .any(|conn| conn.id == handle.id && conn.player_uuid == Some(uuid)));
+
+        drop(client);
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+        assert!(CONNECTION_MANAGER.player(uuid).is_none());
+        assert!(CONNECTION_MANAGER.player_by_name("Lookup").is_none());
+    }
+
+    #[tokio::test]
+    async fn broadcasts_to_play_connections_only() {
+        let manager = ConnectionManager::new();
+        let (old, mut old_queue) = test_handle(1, ConnectionState::Play, 754);
+        let (new, mut new_queue) = test_handle(2, ConnectionState::Play, 756);
+        let (login, mut login_queue) = test_handle(3, ConnectionState::Login, 754);
+        for handle in [old, new, login] {
+            manager.add_connection(handle);
+        }
+
+        let sent = manager
+            .broadcast_to_players(&KeepAliveResponse { id: 7 })
+            .await
+            .unwrap();
+
+        assert_eq!(sent, 2);
+        assert!(matches!(
+            old_queue.try_recv(),
+            Ok(Outgoing::Packet(data)) if data == [0x1f, 0, 0, 0, 0, 0, 0, 0, 7]
+        ));
+        assert!(matches!(
+            new_queue.try_recv(),
+            Ok(Outgoing::Packet(data)) if data == [0x21, 0, 0, 0, 0, 0, 0, 0, 7]
+        ));
+        assert!(login_queue.try_recv().is_err());
+    }
+
+    #[test]
+    fn samples_online_players() {
+        let manager = ConnectionManager::new();
+        let mut queues = Vec::new();
+        for (id, name) in [(1, "Alpha"), (2, "Beta"), (3, "Gamma")] {
+            let (handle, queue) = test_handle(id, ConnectionState::Play, PROTOCOL);
+            manager.add_player(offline_uuid(name), test_player(name, &handle));
+            queues.push(queue);
+        }
+
+        assert_eq!(manager.player_sample(2).len(), 2);
+        let mut sample = manager.player_sample(12);
+        sample.sort_by(|a, b| a.name.cmp(&b.name));
+        let sample: Vec<(String, String, u32)> = sample
+            .into_iter()
+            .map(|player| (player.name, player.id, player.latency))
+            .collect();
+        assert_eq!(
+            sample,
+            ["Alpha", "Beta", "Gamma"].map(|name| (
+                name.to_string(),
+                offline_uuid(name).hyphenated().to_string(),
+                0
+            ))
+        );
+    }
+}
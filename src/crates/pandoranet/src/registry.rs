@@ -0,0 +1,117 @@
+//! The packet registry, generated by `build.rs` from `protocol.json`.
+//!
+//! Every packet of every supported protocol version is one entry in
+//! `protocol.json`, keyed by state, direction and id. Entries that name a Rust
+//! type get a `Packet` impl, so the type knows its id when it is encoded, and
+//! serverbound ones are decoded by id and passed to their handler by
+//! `dispatch_serverbound`. Adding a packet takes an entry and a struct deriving
+//! `Encode` or `Decode`, plus a handler for serverbound packets.
+
+use packet_manager::{types::varint_types::VarInt, ProtocolError};
+use pandora_utils::config::Config;
+use pandora_utils::types::{
+    encode_types::Encode,
+    packet_types::{Packet, PacketDirection, PacketState},
+};
+
+use crate::handler::ServerContext;
+use crate::structs::protocol::JoinGameResponse;
+use crate::Connection;
+
+include!(concat!(env!("OUT_DIR"), "/registry.rs"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    /// Name of the release, such as `1.16.5`.
+    pub name: &'static str,
+    pub protocol: i32,
+}
+
+impl ProtocolVersion {
+    /// Whether players on this version get into the world, which takes a Join
+    /// Game in the registry. Versions without one only work up to login.
+    pub fn is_playable(&self) -> bool {
+        JoinGameResponse::id(self.protocol).is_some()
+    }
+}
+
+/// The protocol versions players may join with, oldest first. These are the
+/// versions named in `Config::versions`, or every playable version of the
+/// registry when it names none of them.
+pub fn enabled_versions() -> Vec<ProtocolVersion> {
+    let config = Config::get();
+    let enabled: Vec<ProtocolVersion> = VERSIONS
+        .iter()
+        .filter(|version| config.versions.iter().any(|name| name == version.name))
+        .copied()
+        .collect();
+
+    if enabled.is_empty() {
+        VERSIONS
+            .iter()
+            .filter(|version| version.is_playable())
+            .copied()
+            .collect()
+    } else {
+        enabled
+    }
+}
+
+/// The enabled version with protocol number `protocol`.
+pub fn enabled_version(protocol: i32) -> Option<ProtocolVersion> {
+    enabled_versions()
+        .into_iter()
+        .find(|version| version.protocol == protocol)
+}
+
+/// Names of the enabled versions, as shown to players: `1.16.5, 1.17.1`.
+pub fn enabled_version_names() -> String {
+    enabled_versions()
+        .iter()
+        .map(|version| version.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Encodes `packet` behind its id in protocol version `protocol`.
+pub fn encode_packet<P: Packet + Encode>(
+    protocol: i32,
+    packet: &P,
+) -> Result<Vec<u8>, ProtocolError> {
+    let packet_id = P::id(protocol).ok_or(ProtocolError::Unregistered {
+        packet: P::NAME,
+        protocol,
+    })?;
+
+    let mut bytes = Vec::new();
+    VarInt::new(packet_id).encode(&mut bytes)?;
+    packet.encode(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::init;
+
+    #[test]
+    fn enables_every_playable_version_by_default() {
+        init();
+
+        let names: Vec<&str> = enabled_versions().iter().map(|v| v.name).collect();
+        assert_eq!(names, ["1.16.5", "1.17.1"]);
+        assert_eq!(enabled_version_names(), "1.16.5, 1.17.1");
+        assert_eq!(enabled_version(756).map(|v| v.name), Some("1.17.1"));
+        assert!(enabled_version(755).is_none());
+    }
+
+    #[test]
+    fn knows_which_versions_reach_the_world() {
+        let playable: Vec<(&str, bool)> = VERSIONS
+            .iter()
+            .map(|version| (version.name, version.is_playable()))
+            .collect();
+        assert_eq!(playable, [("1.16.5", true), ("1.17.1", true)]);
+    }
+}
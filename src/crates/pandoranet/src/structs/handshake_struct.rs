@@ -1,22 +1,24 @@
 use std::fmt::Display;
 
+use async_trait::async_trait;
 use log::debug;
-use packet_manager::types::varint_types::VarInt;
+use packet_manager::{types::varint_types::VarInt, ProtocolError};
 use pandora_macros::Decode;
-use pandora_utils::types::decode_types::Decode;
-use serde_json::json;
-use tokio::io::AsyncRead;
-use tokio::io::AsyncSeek;
+use serde_json::{json, Value};
 
-use crate::structs::protocol::ClientHandshakeRequest;
-use crate::Connection;
+use crate::handler::{PacketHandler, ServerContext};
+use crate::registry::{enabled_version, enabled_version_names, enabled_versions};
+use crate::structs::login_struct::disconnect_with;
+use crate::{Connection, ConnectionState};
 
 #[derive(Decode, Debug)]
+#[packet(id = 0x00, state = Handshake, direction = Serverbound)]
 pub struct HandshakePacket {
-    protocol_version: VarInt,
-    server_address: String,
-    server_port: u16,
-    next_state: VarInt,
+    pub protocol_version: VarInt,
+    #[max_len = 255]
+    pub server_address: String,
+    pub server_port: u16,
+    pub next_state: VarInt,
 }
 
 impl Display for HandshakePacket {
@@ -29,39 +31,66 @@ impl Display for HandshakePacket {
     }
 }
 
-impl HandshakePacket {
-    pub async fn handle(self, conn: &mut Connection) -> Result<(), String> {
-        debug!("Handshake packet received");
+pub struct HandshakeHandler;
 
-        let response_data = json!({
-            "version": {
-                "name": "1.16.5",
-                "protocol": 754,
-            },
-            "players": {
-                "max": 10,
-                "online": 0,
-            },
-            "description": {
-                "text": "A Minecraft server",
-            },
-        });
+#[async_trait]
+impl PacketHandler<HandshakePacket> for HandshakeHandler {
+    async fn handle(
+        &self,
+        packet: HandshakePacket,
+        conn: &mut Connection,
+        _server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        debug!("{}", packet);
 
-        let packet = ClientHandshakeRequest {
-            packet_id: VarInt::new(0x00),
-            res_json: response_data.to_string(),
+        let next_state = match packet.next_state.to_i32() {
+            1 => ConnectionState::Status,
+            2 => ConnectionState::Login,
+            other => {
+                return Err(ProtocolError::InvalidData {
+                    field: "HandshakePacket.next_state",
+                    reason: format!("unknown state {}", other),
+                })
+            }
         };
 
-        let data = packet
-            .encode()
-            .await
-            .map_err(|err| {
-                log::error!("Failed to encode handshake packet {:?}", err);
-            })
-            .unwrap();
+        // Clients on other versions can still ping the server, their status
+        // response and login disconnect are encoded with the newest version.
+        let client_protocol = packet.protocol_version.to_i32();
+        let version = enabled_version(client_protocol);
+        conn.protocol_version = match version {
+            Some(version) => version.protocol,
+            None => enabled_versions().last().unwrap().protocol,
+        };
+
+        conn.set_state(next_state);
 
-        conn.push_to_queue(data).await;
+        if version.is_none() && conn.state == ConnectionState::Login {
+            debug!(
+                "Rejecting connection {} with protocol {}",
+                conn.id, client_protocol
+            );
+            return disconnect_with(conn, outdated_message(client_protocol)).await;
+        }
 
         Ok(())
     }
 }
+
+/// The translated message vanilla servers send to clients on another version.
+fn outdated_message(client_protocol: i32) -> Value {
+    let versions = enabled_versions();
+    let newest = versions.last().unwrap();
+
+    if client_protocol > newest.protocol {
+        json!({
+            "translate": "multiplayer.disconnect.outdated_server",
+            "with": [newest.name],
+        })
+    } else {
+        json!({
+            "translate": "multiplayer.disconnect.outdated_client",
+            "with": [enabled_version_names()],
+        })
+    }
+}
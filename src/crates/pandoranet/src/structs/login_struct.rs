@@ -0,0 +1,464 @@
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use md5::{Digest, Md5};
+use packet_manager::ProtocolError;
+use pandora_macros::Decode;
+use pandora_utils::config::Config;
+use pandora_utils::types::packet_types::Packet;
+use serde_json::{json, Value};
+use uuid::{Builder, Uuid};
+
+use crate::actions::{PlayerAction, PLAYER_ACTIONS};
+use crate::auth::{has_joined, server_hash, server_key, GameProfile};
+use crate::handler::{PacketHandler, ServerContext};
+use crate::registry::encode_packet;
+use crate::structs::play_struct::send_join_game;
+use crate::structs::protocol::{
+    EncryptionRequestResponse, LoginDisconnectResponse, LoginSuccessResponse,
+    PlayDisconnectResponse, SetCompressionResponse,
+};
+use crate::{Connection, ConnectionState, OnlinePlayer, CONNECTION_MANAGER};
+
+#[derive(Decode, Debug)]
+pub struct LoginStartPacket {
+    #[max_len = 16]
+    pub name: String,
+}
+
+pub struct LoginStartHandler;
+
+#[async_trait]
+impl PacketHandler<LoginStartPacket> for LoginStartHandler {
+    async fn handle(
+        &self,
+        packet: LoginStartPacket,
+        conn: &mut Connection,
+        server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        debug!("Login start received for {}", packet.name);
+
+        if !is_valid_username(&packet.name) {
+            return disconnect(conn, "Invalid username").await;
+        }
+
+        if !server.config.online_mode {
+            let profile = GameProfile {
+                id: offline_uuid(&packet.name),
+                name: packet.name,
+                properties: Vec::new(),
+            };
+            return finish_login(conn, profile).await;
+        }
+
+        let verify_token = rand::random::<[u8; 4]>().to_vec();
+
+        let request = EncryptionRequestResponse {
+            server_id: String::new(),
+            public_key: server_key().public_key_der.clone(),
+            verify_token: verify_token.clone(),
+        };
+
+        let data = encode_packet(conn.protocol_version, &request).map_err(|err| {
+            log::error!("Failed to encode encryption request {:?}", err);
+            err
+        })?;
+
+        conn.push_to_queue(data).await;
+        conn.pending_login = Some(PendingLogin {
+            name: packet.name,
+            verify_token,
+        });
+
+        Ok(())
+    }
+}
+
+/// Login state kept between Encryption Request and Encryption Response.
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub name: String,
+    pub verify_token: Vec<u8>,
+}
+
+#[derive(Decode, Debug)]
+pub struct EncryptionResponsePacket {
+    pub shared_secret: Vec<u8>,
+    pub verify_token: Vec<u8>,
+}
+
+pub struct EncryptionResponseHandler;
+
+#[async_trait]
+impl PacketHandler<EncryptionResponsePacket> for EncryptionResponseHandler {
+    async fn handle(
+        &self,
+        packet: EncryptionResponsePacket,
+        conn: &mut Connection,
+        server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        let Some(pending) = conn.pending_login.take() else {
+            return Err(ProtocolError::BadState {
+                packet_id: EncryptionResponsePacket::id(conn.protocol_version).unwrap_or_default(),
+                state: format!("{:?}", conn.state),
+            });
+        };
+
+        let key = server_key();
+
+        match key.decrypt(&packet.verify_token) {
+            Ok(token) if token == pending.verify_token => {}
+            _ => return disconnect(conn, "Invalid verify token").await,
+        }
+
+        let shared_secret = match key.decrypt(&packet.shared_secret) {
+            Ok(secret) if secret.len() == 16 => secret,
+            _ => return disconnect(conn, "Invalid shared secret").await,
+        };
+
+        let hash = server_hash("", &shared_secret, &key.public_key_der);
+        let session_server = server.config.session_server.clone();
+
+        conn.enable_encryption(&shared_secret).await?;
+
+        match has_joined(&session_server, &pending.name, &hash).await {
+            Ok(Some(profile)) => finish_login(conn, profile).await,
+            Ok(None) => disconnect(conn, "Failed to verify username!").await,
+            Err(e) => {
+                warn!("Could not verify {}: {}", pending.name, e);
+                disconnect(
+                    conn,
+                    "Authentication servers are down. Please try again later, sorry!",
+                )
+                .await
+            }
+        }
+    }
+}
+
+/// Usernames follow the Mojang account rules: 3 to 16 characters of `[A-Za-z0-9_]`.
+pub fn is_valid_username(name: &str) -> bool {
+    (3..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Computes the UUID an offline-mode server assigns to `name`, matching Java's
+/// `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{}", name).as_bytes());
+    Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+/// Completes the login of an authenticated player: kicks any older session using the
+/// same name, enables compression, sends Login Success, moves the connection to
+/// the play state, sends Join Game and tells the game loop the player joined.
+pub async fn finish_login(
+    conn: &mut Connection,
+    profile: GameProfile,
+) -> Result<(), ProtocolError> {
+    let GameProfile {
+        id: uuid,
+        name,
+        properties,
+    } = profile;
+
+    if let Some(previous) = CONNECTION_MANAGER.find_player(uuid, &name) {
+        info!(
+            "{} logged in again, closing connection {}",
+            name, previous.connection_id
+        );
+        previous.kick("You logged in from another location");
+    }
+
+    let threshold = Config::get().compression_threshold;
+    if threshold >= 0 {
+        let packet = SetCompressionResponse { threshold };
+
+        let data = encode_packet(conn.protocol_version, &packet).map_err(|err| {
+            log::error!("Failed to encode set compression {:?}", err);
+            err
+        })?;
+
+        conn.push_to_queue(data).await;
+        conn.enable_compression(threshold).await;
+    }
+
+    let packet = LoginSuccessResponse {
+        uuid,
+        username: name.clone(),
+    };
+
+    let data = encode_packet(conn.protocol_version, &packet).map_err(|err| {
+        log::error!("Failed to encode login success {:?}", err);
+        err
+    })?;
+
+    conn.push_to_queue(data).await;
+
+    conn.player_uuid = Some(uuid);
+    conn.player_name = Some(name.clone());
+
+    let player = OnlinePlayer {
+        name: name.clone(),
+        connection_id: conn.id,
+        protocol_version: conn.protocol_version,
+        send_queue_sender: conn.send_queue_sender.clone(),
+        shutdown: conn.shutdown.clone(),
+        latency: conn.keep_alive.latency().clone(),
+        properties: properties.into(),
+    };
+    CONNECTION_MANAGER.add_player(uuid, player.clone());
+    conn.set_state(ConnectionState::Play);
+
+    info!("{} ({}) logged in", name, uuid);
+
+    send_join_game(conn).await?;
+    PLAYER_ACTIONS.send(PlayerAction::Joined { uuid, player });
+
+    Ok(())
+}
+
+/// Encodes the disconnect packet of `state` carrying the chat component `reason`.
+/// Clients only show a reason during login and play, other states have no
+/// disconnect packet.
+pub fn disconnect_packet(
+    protocol: i32,
+    state: &ConnectionState,
+    reason: &Value,
+) -> Result<Option<Vec<u8>>, ProtocolError> {
+    let reason = reason.to_string();
+
+    let data = match state {
+        ConnectionState::Login => encode_packet(protocol, &LoginDisconnectResponse { reason }),
+        ConnectionState::Play => encode_packet(protocol, &PlayDisconnectResponse { reason }),
+        _ => return Ok(None),
+    }
+    .map_err(|err| {
+        log::error!("Failed to encode disconnect {:?}", err);
+        err
+    })?;
+
+    Ok(Some(data))
+}
+
+/// Sends a disconnect packet with `reason` and closes the connection.
+pub async fn disconnect(conn: &mut Connection, reason: &str) -> Result<(), ProtocolError> {
+    disconnect_with(conn, json!({ "text": reason })).await
+}
+
+/// Sends a disconnect packet with the chat component `reason` and closes the
+/// connection.
+pub async fn disconnect_with(conn: &mut Connection, reason: Value) -> Result<(), ProtocolError> {
+    debug!("Disconnecting {}: {}", conn.id, reason);
+
+    if let Some(data) = disconnect_packet(conn.protocol_version, &conn.state, &reason)? {
+        conn.push_to_queue(data).await;
+    }
+    conn.close();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pandora_utils::types::decode_types::Decode;
+    use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+
+    use super::*;
+    use crate::test_util::{
+        connect, finished, lock_config, mock_http_server, override_config, test_config, TestClient,
+    };
+    use crate::DisconnectReason;
+
+    const SHARED_SECRET: [u8; 16] = *b"0123456789abcdef";
+
+    fn online_config(session_server: &str) -> Config {
+        let mut config = test_config();
+        config.online_mode = true;
+        config.session_server = session_server.to_string();
+        config
+    }
+
+    /// Sends Login Start for `name` and returns the server's public key and
+    /// verify token from Encryption Request.
+    async fn request_encryption(client: &mut TestClient, name: &str) -> (RsaPublicKey, Vec<u8>) {
+        client.handshake(2).await;
+        client.send(0x00, &[&name.to_string()]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x01);
+        let mut data = &data[..];
+        assert_eq!(String::decode(&mut data).unwrap(), "");
+        let public_key = Vec::<u8>::decode(&mut data).unwrap();
+        let verify_token = Vec::<u8>::decode(&mut data).unwrap();
+        assert!(data.is_empty());
+
+        (
+            RsaPublicKey::from_public_key_der(&public_key).unwrap(),
+            verify_token,
+        )
+    }
+
+    /// Sends Encryption Response with `SHARED_SECRET` and `verify_token`, both
+    /// encrypted with `public_key`.
+    async fn respond_to_encryption(
+        client: &mut TestClient,
+        public_key: &RsaPublicKey,
+        verify_token: &[u8],
+    ) {
+        let encrypt = |data: &[u8]| {
+            public_key
+                .encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, data)
+                .unwrap()
+        };
+        client
+            .send(0x01, &[&encrypt(&SHARED_SECRET), &encrypt(verify_token)])
+            .await;
+    }
+
+    fn disconnect_reason(data: &[u8]) -> Value {
+        serde_json::from_str(&String::decode(&mut &data[..]).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn offline_uuids_match_the_vanilla_server() {
+        assert_eq!(
+            offline_uuid("Tester").hyphenated().to_string(),
+            "f3d28cb0-7225-3cb1-baeb-2dadd2be89ae"
+        );
+        assert_eq!(offline_uuid("Tester").get_version_num(), 3);
+        assert_ne!(offline_uuid("tester"), offline_uuid("Tester"));
+    }
+
+    #[test]
+    fn accepts_only_mojang_usernames() {
+        assert!(!is_valid_username("ab"));
+        assert!(is_valid_username("abc"));
+        assert!(is_valid_username("Sixteen_chars_16"));
+        assert!(!is_valid_username("Seventeen_chars17"));
+        assert!(!is_valid_username(""));
+        assert!(!is_valid_username("Tes-ter"));
+        assert!(!is_valid_username("Tes ter"));
+        assert!(!is_valid_username("Tésteur"));
+    }
+
+    #[tokio::test]
+    async fn disconnects_invalid_usernames() {
+        let (mut client, _, task) = connect().await;
+
+        client.handshake(2).await;
+        client.send(0x00, &[&"No-dash".to_string()]).await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x00);
+        let reason = String::decode(&mut &data[..]).unwrap();
+        assert_eq!(reason, r#"{"text":"Invalid username"}"#);
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+
+    #[tokio::test]
+    async fn a_second_login_kicks_the_first() {
+        let _config = lock_config().await;
+        let (mut first, _, first_task) = connect().await;
+        first.handshake(2).await;
+        first.send(0x00, &[&"Twice".to_string()]).await;
+        assert_eq!(first.receive().await.0, 0x02);
+        assert_eq!(first.receive().await.0, 0x24);
+
+        let (mut second, second_handle, second_task) = connect().await;
+        second.handshake(2).await;
+        second.send(0x00, &[&"Twice".to_string()]).await;
+
+        let (packet_id, data) = first.receive().await;
+        assert_eq!(packet_id, 0x19);
+        let reason = String::decode(&mut &data[..]).unwrap();
+        assert_eq!(reason, r#"{"text":"You logged in from another location"}"#);
+        assert_eq!(finished(first_task).await, DisconnectReason::Shutdown);
+
+        assert_eq!(second.receive().await.0, 0x02);
+        assert_eq!(second.receive().await.0, 0x24);
+        let player = CONNECTION_MANAGER.player(offline_uuid("Twice")).unwrap();
+        assert_eq!(player.connection_id, second_handle.id);
+
+        drop(second);
+        assert_eq!(finished(second_task).await, DisconnectReason::ClosedByPeer);
+    }
+
+    #[tokio::test]
+    async fn logs_in_with_the_profile_from_the_session_server() {
+        let body = r#"{"id":"5f8eb73b25be4c5aa50fd27d65e30ca0","name":"OnlineTester","properties":[{"name":"textures","value":"e30=","signature":"c2ln"}]}"#;
+        let (session_server, request) = mock_http_server(format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+        .await;
+        let _config = override_config(online_config(&session_server)).await;
+        let (mut client, handle, task) = connect().await;
+
+        let (public_key, verify_token) = request_encryption(&mut client, "onlinetester").await;
+        respond_to_encryption(&mut client, &public_key, &verify_token).await;
+        client.enable_encryption(&SHARED_SECRET);
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x02);
+        let uuid = Uuid::parse_str("5f8eb73b-25be-4c5a-a50f-d27d65e30ca0").unwrap();
+        let mut data = &data[..];
+        assert_eq!(Uuid::decode(&mut data).unwrap(), uuid);
+        assert_eq!(String::decode(&mut data).unwrap(), "OnlineTester");
+        assert_eq!(client.receive().await.0, 0x24);
+
+        let request = request.await.unwrap();
+        assert!(
+            request.contains("username=onlinetester&serverId="),
+            "{}",
+            request
+        );
+        let player = CONNECTION_MANAGER.player(uuid).unwrap();
+        assert_eq!(player.connection_id, handle.id);
+        assert_eq!(player.properties[0].name, "textures");
+
+        drop(client);
+        assert_eq!(finished(task).await, DisconnectReason::ClosedByPeer);
+    }
+
+    #[tokio::test]
+    async fn disconnects_players_the_session_server_does_not_know() {
+        let (session_server, _) =
+            mock_http_server("HTTP/1.1 204 No Content\r\n\r\n".to_string()).await;
+        let _config = override_config(online_config(&session_server)).await;
+        let (mut client, _, task) = connect().await;
+
+        let (public_key, verify_token) = request_encryption(&mut client, "Unverified").await;
+        respond_to_encryption(&mut client, &public_key, &verify_token).await;
+        client.enable_encryption(&SHARED_SECRET);
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x00);
+        assert_eq!(
+            disconnect_reason(&data),
+            json!({ "text": "Failed to verify username!" })
+        );
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+        assert!(CONNECTION_MANAGER
+            .find_player(offline_uuid("Unverified"), "Unverified")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn disconnects_on_a_wrong_verify_token() {
+        let _config = override_config(online_config("http://127.0.0.1:1")).await;
+        let (mut client, _, task) = connect().await;
+
+        let (public_key, mut verify_token) = request_encryption(&mut client, "Forger").await;
+        verify_token[0] ^= 0xFF;
+        respond_to_encryption(&mut client, &public_key, &verify_token).await;
+
+        // Encryption is only enabled once the token matched.
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x00);
+        assert_eq!(
+            disconnect_reason(&data),
+            json!({ "text": "Invalid verify token" })
+        );
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+}
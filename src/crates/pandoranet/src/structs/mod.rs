@@ -1,2 +1,5 @@
 pub mod handshake_struct;
+pub mod login_struct;
+pub mod play_struct;
 pub mod protocol;
+pub mod status_struct;
@@ -0,0 +1,615 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+use nbt::Value;
+use packet_manager::ProtocolError;
+use pandora_macros::Decode;
+use pandora_utils::{
+    config::Config,
+    read_file::read_file,
+    types::{decode_types::Decode, nbt_types::RawNbt, packet_types::Packet},
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::time::Instant;
+
+use crate::actions::{Movement, PlayerAction, PLAYER_ACTIONS};
+use crate::handler::{PacketHandler, ServerContext};
+use crate::registry::encode_packet;
+use crate::structs::login_struct::disconnect_with;
+use crate::structs::protocol::JoinGameResponse;
+use crate::Connection;
+
+const DIMENSION_CODEC_PATH: &str = "assets/dimension_codec.nbt";
+/// The world players join in, named in the dimension codec.
+const OVERWORLD: &str = "minecraft:overworld";
+/// 1.17, the first protocol whose dimension types give the world height.
+const WORLD_HEIGHT_PROTOCOL: i32 = 755;
+/// The world height of 1.16.5, given to 1.17 clients in the same world.
+const MIN_Y: i32 = 0;
+const HEIGHT: i32 = 256;
+/// Players are kept within these coordinates, the vanilla world limits.
+const MAX_HORIZONTAL: f64 = 3.0e7;
+const MAX_VERTICAL: f64 = 2.0e7;
+
+lazy_static! {
+    static ref DIMENSION_CODEC: DimensionCodec = DimensionCodec::load(DIMENSION_CODEC_PATH)
+        .unwrap_or_else(|err| {
+            error!("{}", err);
+            panic!("Dimension codec missing or malformed");
+        });
+    static ref DIMENSION_CODEC_WITH_HEIGHT: DimensionCodec =
+        DIMENSION_CODEC.with_world_height().unwrap_or_else(|err| {
+            error!("{}", err);
+            panic!("Dimension codec malformed");
+        });
+}
+
+static NEXT_ENTITY_ID: AtomicI32 = AtomicI32::new(1);
+
+/// A chat message or command typed by the player. The server has no handler for
+/// it, one can be registered with `PACKET_HANDLERS`.
+#[derive(Decode, Debug)]
+pub struct ChatMessagePacket {
+    #[max_len = 256]
+    pub message: String,
+}
+
+/// The client's answer to a Keep Alive, echoing its id.
+#[derive(Decode, Debug)]
+pub struct KeepAlivePacket {
+    pub id: i64,
+}
+
+pub struct KeepAliveHandler;
+
+#[async_trait]
+impl PacketHandler<KeepAlivePacket> for KeepAliveHandler {
+    async fn handle(
+        &self,
+        packet: KeepAlivePacket,
+        conn: &mut Connection,
+        _server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        match conn.keep_alive.acknowledge(packet.id, Instant::now()) {
+            Some(round_trip) => debug!(
+                "Keep alive from connection {} after {:?}",
+                conn.id, round_trip
+            ),
+            None => debug!(
+                "Ignoring keep alive {} from connection {}, it was not sent or already answered",
+                packet.id, conn.id
+            ),
+        }
+
+        Ok(())
+    }
+}
+
+/// Sent once the client has moved to the position of a Player Position And Look.
+#[derive(Decode, Debug)]
+pub struct TeleportConfirmPacket {
+    #[varint]
+    pub teleport_id: i32,
+}
+
+pub struct TeleportConfirmHandler;
+
+#[async_trait]
+impl PacketHandler<TeleportConfirmPacket> for TeleportConfirmHandler {
+    async fn handle(
+        &self,
+        packet: TeleportConfirmPacket,
+        conn: &mut Connection,
+        _server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        if let Some(uuid) = conn.player_uuid {
+            PLAYER_ACTIONS.send(PlayerAction::TeleportConfirmed {
+                uuid,
+                connection_id: conn.id,
+                teleport_id: packet.teleport_id,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Decode, Debug)]
+pub struct PlayerPositionPacket {
+    pub x: f64,
+    pub feet_y: f64,
+    pub z: f64,
+    pub on_ground: bool,
+}
+
+#[derive(Decode, Debug)]
+pub struct PlayerPositionAndRotationPacket {
+    pub x: f64,
+    pub feet_y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+#[derive(Decode, Debug)]
+pub struct PlayerRotationPacket {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+/// Sent while the player neither moves nor turns.
+#[derive(Decode, Debug)]
+pub struct PlayerOnGroundPacket {
+    pub on_ground: bool,
+}
+
+impl From<PlayerPositionPacket> for Movement {
+    fn from(packet: PlayerPositionPacket) -> Self {
+        Movement {
+            position: Some((packet.x, packet.feet_y, packet.z)),
+            rotation: None,
+            on_ground: packet.on_ground,
+        }
+    }
+}
+
+impl From<PlayerPositionAndRotationPacket> for Movement {
+    fn from(packet: PlayerPositionAndRotationPacket) -> Self {
+        Movement {
+            position: Some((packet.x, packet.feet_y, packet.z)),
+            rotation: Some((packet.yaw, packet.pitch)),
+            on_ground: packet.on_ground,
+        }
+    }
+}
+
+impl From<PlayerRotationPacket> for Movement {
+    fn from(packet: PlayerRotationPacket) -> Self {
+        Movement {
+            position: None,
+            rotation: Some((packet.yaw, packet.pitch)),
+            on_ground: packet.on_ground,
+        }
+    }
+}
+
+impl From<PlayerOnGroundPacket> for Movement {
+    fn from(packet: PlayerOnGroundPacket) -> Self {
+        Movement {
+            position: None,
+            rotation: None,
+            on_ground: packet.on_ground,
+        }
+    }
+}
+
+/// Passes the four movement packets on to the game loop. Clients that send
+/// coordinates or angles that are not finite numbers are disconnected.
+pub struct MovementHandler;
+
+macro_rules! movement_handler {
+    ($($packet:ty),* $(,)?) => {
+        $(
+            #[async_trait]
+            impl PacketHandler<$packet> for MovementHandler {
+                async fn handle(
+                    &self,
+                    packet: $packet,
+                    conn: &mut Connection,
+                    _server: &ServerContext,
+                ) -> Result<(), ProtocolError> {
+                    handle_movement(conn, packet.into()).await
+                }
+            }
+        )*
+    };
+}
+
+movement_handler!(
+    PlayerPositionPacket,
+    PlayerPositionAndRotationPacket,
+    PlayerRotationPacket,
+    PlayerOnGroundPacket,
+);
+
+async fn handle_movement(conn: &mut Connection, movement: Movement) -> Result<(), ProtocolError> {
+    let Some(uuid) = conn.player_uuid else {
+        return Ok(());
+    };
+
+    let Some(movement) = clamp_movement(movement) else {
+        warn!(
+            "{} sent an invalid move: {:?}",
+            conn.player_name.as_deref().unwrap_or("?"),
+            movement
+        );
+        return disconnect_with(
+            conn,
+            json!({ "translate": "multiplayer.disconnect.invalid_player_movement" }),
+        )
+        .await;
+    };
+
+    PLAYER_ACTIONS.send(PlayerAction::Moved {
+        uuid,
+        connection_id: conn.id,
+        movement,
+    });
+
+    Ok(())
+}
+
+/// Clamps the position to the world limits, the pitch to straight up or down
+/// and wraps the yaw to -180..180 degrees. Returns `None` if any of them is NaN
+/// or infinite.
+pub fn clamp_movement(movement: Movement) -> Option<Movement> {
+    let position = match movement.position {
+        Some((x, y, z)) if x.is_finite() && y.is_finite() && z.is_finite() => Some((
+            x.clamp(-MAX_HORIZONTAL, MAX_HORIZONTAL),
+            y.clamp(-MAX_VERTICAL, MAX_VERTICAL),
+            z.clamp(-MAX_HORIZONTAL, MAX_HORIZONTAL),
+        )),
+        Some(_) => return None,
+        None => None,
+    };
+
+    let rotation = match movement.rotation {
+        Some((yaw, pitch)) if yaw.is_finite() && pitch.is_finite() => {
+            Some((wrap_degrees(yaw), pitch.clamp(-90.0, 90.0)))
+        }
+        Some(_) => return None,
+        None => None,
+    };
+
+    Some(Movement {
+        position,
+        rotation,
+        on_ground: movement.on_ground,
+    })
+}
+
+/// Wraps an angle in degrees to -180..180.
+pub fn wrap_degrees(degrees: f32) -> f32 {
+    let degrees = degrees % 360.0;
+    if degrees >= 180.0 {
+        degrees - 360.0
+    } else if degrees < -180.0 {
+        degrees + 360.0
+    } else {
+        degrees
+    }
+}
+
+/// The dimension and biome registries sent in Join Game, encoded once.
+#[derive(Debug)]
+pub struct DimensionCodec {
+    pub codec: RawNbt,
+    /// The dimension type of the overworld.
+    pub overworld: RawNbt,
+}
+
+impl DimensionCodec {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let blob = read_file(path).map_err(|err| err.to_string())?;
+        let codec = RawNbt::from_blob(&blob)
+            .map_err(|err| format!("Failed to encode {}: {}", path, err))?;
+
+        let registries = Value::decode(&mut codec.as_bytes())
+            .map_err(|err| format!("Failed to read {}: {}", path, err))?;
+
+        Self::new(codec, &registries).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn new(codec: RawNbt, registries: &Value) -> Result<Self, String> {
+        let overworld = dimension_types(registries)
+            .into_iter()
+            .find_map(|(name, element)| (name == OVERWORLD).then_some(element))
+            .ok_or_else(|| format!("no dimension type {}", OVERWORLD))?;
+        let overworld = RawNbt::from_value(overworld)
+            .map_err(|err| format!("Failed to encode {}: {}", OVERWORLD, err))?;
+
+        Ok(DimensionCodec { codec, overworld })
+    }
+
+    /// The codec for 1.17 and newer, whose dimension types also need `min_y`
+    /// and `height`. Dimension types without them get the 1.16.5 world height.
+    pub fn with_world_height(&self) -> Result<Self, String> {
+        let mut registries = Value::decode(&mut self.codec.as_bytes())
+            .map_err(|err| format!("Failed to read the dimension codec: {}", err))?;
+
+        for element in dimension_types_mut(&mut registries) {
+            if let Value::Compound(element) = element {
+                element
+                    .entry("min_y".to_string())
+                    .or_insert(Value::Int(MIN_Y));
+                element
+                    .entry("height".to_string())
+                    .or_insert(Value::Int(HEIGHT));
+            }
+        }
+
+        let codec = RawNbt::from_value(&registries)
+            .map_err(|err| format!("Failed to encode the dimension codec: {}", err))?;
+        Self::new(codec, &registries)
+    }
+}
+
+/// The entries of the `minecraft:dimension_type` registry, by name.
+fn dimension_types(registries: &Value) -> Vec<(&str, &Value)> {
+    let Value::Compound(registries) = registries else {
+        return Vec::new();
+    };
+    let Some(Value::Compound(registry)) = registries.get("minecraft:dimension_type") else {
+        return Vec::new();
+    };
+    let Some(Value::List(entries)) = registry.get("value") else {
+        return Vec::new();
+    };
+
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let Value::Compound(entry) = entry else {
+                return None;
+            };
+            let Value::String(name) = entry.get("name")? else {
+                return None;
+            };
+            Some((name.as_str(), entry.get("element")?))
+        })
+        .collect()
+}
+
+/// The elements of the `minecraft:dimension_type` registry.
+fn dimension_types_mut(registries: &mut Value) -> Vec<&mut Value> {
+    let Value::Compound(registries) = registries else {
+        return Vec::new();
+    };
+    let Some(Value::Compound(registry)) = registries.get_mut("minecraft:dimension_type") else {
+        return Vec::new();
+    };
+    let Some(Value::List(entries)) = registry.get_mut("value") else {
+        return Vec::new();
+    };
+
+    entries
+        .iter_mut()
+        .filter_map(|entry| match entry {
+            Value::Compound(entry) => entry.get_mut("element"),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the dimension codec for protocol version `protocol`, loading
+/// `assets/dimension_codec.nbt` on first use.
+pub fn dimension_codec(protocol: i32) -> &'static DimensionCodec {
+    if protocol >= WORLD_HEIGHT_PROTOCOL {
+        &DIMENSION_CODEC_WITH_HEIGHT
+    } else {
+        &DIMENSION_CODEC
+    }
+}
+
+/// Hashes the world seed the way the vanilla server does: the first 8 bytes of
+/// the SHA-256 digest of the little endian seed, read as a little endian long.
+pub fn hashed_seed(seed: i64) -> i64 {
+    let digest = Sha256::digest(seed.to_le_bytes());
+    i64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+/// Builds the Join Game packet for a new player on `protocol` in the overworld.
+pub fn join_game(entity_id: i32, protocol: i32, config: &Config) -> JoinGameResponse {
+    let codec = dimension_codec(protocol);
+
+    JoinGameResponse {
+        entity_id,
+        is_hardcore: config.hardcore,
+        gamemode: config.gamemode.id(),
+        previous_gamemode: -1,
+        world_names: vec![OVERWORLD.to_string()],
+        dimension_codec: codec.codec.clone(),
+        dimension: codec.overworld.clone(),
+        world_name: OVERWORLD.to_string(),
+        hashed_seed: hashed_seed(config.seed),
+        max_players: config.max_players as i32,
+        view_distance: config.view_distance,
+        reduced_debug_info: config.reduced_debug_info,
+        enable_respawn_screen: config.enable_respawn_screen,
+        is_debug: false,
+        is_flat: config.flat_world,
+    }
+}
+
+/// Sends Join Game to a player that just logged in. Versions without a Join Game
+/// in the registry stay in the loading screen.
+pub async fn send_join_game(conn: &mut Connection) -> Result<(), ProtocolError> {
+    if JoinGameResponse::id(conn.protocol_version).is_none() {
+        debug!(
+            "No Join Game for protocol {}, connection {} stays in the loading screen",
+            conn.protocol_version, conn.id
+        );
+        return Ok(());
+    }
+
+    let entity_id = NEXT_ENTITY_ID.fetch_add(1, Ordering::Relaxed);
+    let packet = join_game(entity_id, conn.protocol_version, &Config::get());
+
+    let data = encode_packet(conn.protocol_version, &packet).map_err(|err| {
+        log::error!("Failed to encode join game {:?}", err);
+        err
+    })?;
+
+    conn.push_to_queue(data).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use packet_manager::types::varint_types::VarInt;
+
+    use super::*;
+    use crate::test_util::{connect, finished, init, lock_config, PROTOCOL};
+    use crate::DisconnectReason;
+
+    #[test]
+    fn join_game_carries_the_bundled_dimension_codec() {
+        init();
+        let config = Config::get();
+        let expected = join_game(7, PROTOCOL, &config);
+
+        let data = encode_packet(PROTOCOL, &expected).unwrap();
+        let mut data = &data[..];
+        assert_eq!(VarInt::decode(&mut data).unwrap().to_i32(), 0x24);
+        let packet = JoinGameResponse::decode(&mut data).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(packet, expected);
+
+        assert_eq!(
+            packet.dimension_codec.to_blob().unwrap(),
+            read_file(DIMENSION_CODEC_PATH).unwrap()
+        );
+        let overworld = Value::decode(&mut packet.dimension.as_bytes()).unwrap();
+        let Value::Compound(overworld) = overworld else {
+            panic!("dimension is not a compound: {:?}", overworld);
+        };
+        assert_eq!(overworld.get("natural"), Some(&Value::Byte(1)));
+        assert_eq!(packet.world_name, OVERWORLD);
+        assert_eq!(packet.world_names, [OVERWORLD]);
+        assert_eq!(packet.entity_id, 7);
+        assert_eq!(packet.max_players, 20);
+    }
+
+    #[test]
+    fn join_game_since_1_17_gives_the_world_height() {
+        init();
+        let config = Config::get();
+        let expected = join_game(7, 756, &config);
+
+        let data = encode_packet(756, &expected).unwrap();
+        let mut data = &data[..];
+        assert_eq!(VarInt::decode(&mut data).unwrap().to_i32(), 0x26);
+        let packet = JoinGameResponse::decode(&mut data).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(packet, expected);
+
+        let registries = Value::decode(&mut packet.dimension_codec.as_bytes()).unwrap();
+        let types = dimension_types(&registries);
+        assert!(!types.is_empty());
+        for (name, element) in types {
+            let Value::Compound(element) = element else {
+                panic!("{} is not a compound: {:?}", name, element);
+            };
+            assert_eq!(element.get("min_y"), Some(&Value::Int(0)), "{}", name);
+            assert_eq!(element.get("height"), Some(&Value::Int(256)), "{}", name);
+        }
+
+        let Value::Compound(overworld) = Value::decode(&mut packet.dimension.as_bytes()).unwrap()
+        else {
+            panic!("dimension is not a compound");
+        };
+        assert_eq!(overworld.get("height"), Some(&Value::Int(256)));
+        assert_eq!(overworld.get("natural"), Some(&Value::Byte(1)));
+
+        // 1.16.5 clients keep the codec as it is bundled.
+        let registries = Value::decode(&mut dimension_codec(PROTOCOL).codec.as_bytes()).unwrap();
+        for (name, element) in dimension_types(&registries) {
+            let Value::Compound(element) = element else {
+                panic!("{} is not a compound: {:?}", name, element);
+            };
+            assert_eq!(element.get("min_y"), None, "{}", name);
+        }
+    }
+
+    fn movement(position: (f64, f64, f64), rotation: (f32, f32)) -> Movement {
+        Movement {
+            position: Some(position),
+            rotation: Some(rotation),
+            on_ground: true,
+        }
+    }
+
+    #[test]
+    fn wraps_angles_to_half_a_turn_either_way() {
+        assert_eq!(wrap_degrees(0.0), 0.0);
+        assert_eq!(wrap_degrees(179.5), 179.5);
+        assert_eq!(wrap_degrees(180.0), -180.0);
+        assert_eq!(wrap_degrees(-180.0), -180.0);
+        assert_eq!(wrap_degrees(270.0), -90.0);
+        assert_eq!(wrap_degrees(-270.0), 90.0);
+        assert_eq!(wrap_degrees(720.0 + 45.0), 45.0);
+        assert_eq!(wrap_degrees(-1080.0 - 45.0), -45.0);
+    }
+
+    #[test]
+    fn clamps_movement_to_the_world() {
+        let clamped = clamp_movement(movement((4.0e7, -3.0e7, -5.0e7), (450.0, 120.0))).unwrap();
+        assert_eq!(
+            clamped.position,
+            Some((MAX_HORIZONTAL, -MAX_VERTICAL, -MAX_HORIZONTAL))
+        );
+        assert_eq!(clamped.rotation, Some((90.0, 90.0)));
+        assert!(clamped.on_ground);
+
+        let unchanged = movement((1.5, 64.0, -2.5), (-30.0, -45.0));
+        assert_eq!(clamp_movement(unchanged), Some(unchanged));
+
+        let on_ground = Movement {
+            position: None,
+            rotation: None,
+            on_ground: false,
+        };
+        assert_eq!(clamp_movement(on_ground), Some(on_ground));
+    }
+
+    #[test]
+    fn rejects_movement_that_is_not_a_number() {
+        for (position, rotation) in [
+            ((f64::NAN, 64.0, 0.0), (0.0, 0.0)),
+            ((0.0, f64::INFINITY, 0.0), (0.0, 0.0)),
+            ((0.0, 64.0, f64::NEG_INFINITY), (0.0, 0.0)),
+            ((0.0, 64.0, 0.0), (f32::NAN, 0.0)),
+            ((0.0, 64.0, 0.0), (0.0, f32::INFINITY)),
+        ] {
+            assert_eq!(clamp_movement(movement(position, rotation)), None);
+        }
+
+        let rotation_only = Movement {
+            position: None,
+            rotation: Some((f32::NEG_INFINITY, 0.0)),
+            on_ground: true,
+        };
+        assert_eq!(clamp_movement(rotation_only), None);
+    }
+
+    #[tokio::test]
+    async fn disconnects_players_moving_to_an_invalid_position() {
+        let _config = lock_config().await;
+        let (mut client, _handle, task) = connect().await;
+
+        client.handshake(2).await;
+        client.send(0x00, &[&"Glitch".to_string()]).await;
+        assert_eq!(client.receive().await.0, 0x02);
+        assert_eq!(client.receive().await.0, 0x24);
+
+        client
+            .send(0x12, &[&f64::NAN, &64.0f64, &0.0f64, &true])
+            .await;
+
+        let (packet_id, data) = client.receive().await;
+        assert_eq!(packet_id, 0x19);
+        let reason: serde_json::Value =
+            serde_json::from_str(&String::decode(&mut &data[..]).unwrap()).unwrap();
+        assert_eq!(
+            reason,
+            json!({ "translate": "multiplayer.disconnect.invalid_player_movement" })
+        );
+        assert_eq!(finished(task).await, DisconnectReason::Closed);
+    }
+}
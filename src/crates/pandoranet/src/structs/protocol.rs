@@ -1,9 +1,250 @@
-use packet_manager::types::varint_types::VarInt;
-use pandora_macros::Encode;
-use pandora_utils::types::encode_types::Encode;
+use std::ops::BitOr;
+
+use pandora_macros::{Decode, Encode};
+use pandora_utils::types::nbt_types::RawNbt;
+use uuid::Uuid;
+
+use crate::auth::ProfileProperty;
 
 #[derive(Encode, Debug)]
 pub struct ClientHandshakeRequest {
-    pub packet_id: VarInt,
     pub res_json: String,
 }
+
+#[derive(Encode, Debug)]
+pub struct PongResponse {
+    pub payload: i64,
+}
+
+#[derive(Encode, Debug)]
+pub struct LoginDisconnectResponse {
+    pub reason: String,
+}
+
+#[derive(Encode, Debug)]
+pub struct PlayDisconnectResponse {
+    pub reason: String,
+}
+
+#[derive(Encode, Debug)]
+pub struct LoginSuccessResponse {
+    pub uuid: Uuid,
+    #[max_len = 16]
+    pub username: String,
+}
+
+#[derive(Encode, Debug)]
+pub struct EncryptionRequestResponse {
+    #[max_len = 20]
+    pub server_id: String,
+    pub public_key: Vec<u8>,
+    pub verify_token: Vec<u8>,
+}
+
+#[derive(Encode, Debug)]
+pub struct SetCompressionResponse {
+    #[varint]
+    pub threshold: i32,
+}
+
+/// Join Game, in the same layout in 1.16.5 and 1.17.1. The dimension types of
+/// the 1.17.1 codec also carry the world height, see `dimension_codec`.
+#[derive(Encode, Decode, Debug, PartialEq)]
+pub struct JoinGameResponse {
+    pub entity_id: i32,
+    pub is_hardcore: bool,
+    pub gamemode: u8,
+    /// -1 when there is no previous game mode.
+    pub previous_gamemode: i8,
+    pub world_names: Vec<String>,
+    pub dimension_codec: RawNbt,
+    pub dimension: RawNbt,
+    pub world_name: String,
+    /// The first 8 bytes of the SHA-256 hash of the world seed.
+    pub hashed_seed: i64,
+    /// Ignored by clients since 1.16.
+    #[varint]
+    pub max_players: i32,
+    #[varint]
+    pub view_distance: i32,
+    pub reduced_debug_info: bool,
+    pub enable_respawn_screen: bool,
+    pub is_debug: bool,
+    pub is_flat: bool,
+}
+
+#[derive(Encode, Debug)]
+pub struct KeepAliveResponse {
+    pub id: i64,
+}
+
+#[derive(Encode, Debug)]
+pub struct TimeUpdateResponse {
+    pub world_age: i64,
+    /// Negative to stop the sun and moon at the absolute value.
+    pub time_of_day: i64,
+}
+
+/// Player Info, which fills the tab list. All entries of a packet get the same
+/// action.
+#[derive(Encode, Debug)]
+pub enum PlayerInfoResponse {
+    AddPlayer(Vec<PlayerInfoEntry>),
+    #[id = 2]
+    UpdateLatency(Vec<PlayerLatency>),
+    #[id = 4]
+    RemovePlayer(Vec<Uuid>),
+}
+
+#[derive(Encode, Debug)]
+pub struct PlayerInfoEntry {
+    pub uuid: Uuid,
+    #[max_len = 16]
+    pub name: String,
+    pub properties: Vec<ProfileProperty>,
+    #[varint]
+    pub gamemode: i32,
+    /// Round trip time in milliseconds, shown as signal bars.
+    #[varint]
+    pub latency: i32,
+    /// A chat component replacing the name, if any.
+    pub display_name: Option<String>,
+}
+
+#[derive(Encode, Debug)]
+pub struct PlayerLatency {
+    pub uuid: Uuid,
+    #[varint]
+    pub latency: i32,
+}
+
+/// Which Player Position And Look fields are added to the player's current
+/// position and rotation instead of replacing them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TeleportFlags(pub u8);
+
+impl TeleportFlags {
+    pub const ABSOLUTE: Self = Self(0);
+    pub const X: Self = Self(0x01);
+    pub const Y: Self = Self(0x02);
+    pub const Z: Self = Self(0x04);
+    pub const YAW: Self = Self(0x08);
+    pub const PITCH: Self = Self(0x10);
+
+    pub fn contains(self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl BitOr for TeleportFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Player Position And Look in the 1.16.5 layout. The client answers with
+/// Teleport Confirm.
+#[derive(Encode, Debug, Clone)]
+pub struct PlayerPositionAndLookResponse {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// `TeleportFlags` bits.
+    pub flags: u8,
+    #[varint]
+    pub teleport_id: i32,
+}
+
+/// Player Position And Look since 1.17, which added `dismount_vehicle` after the
+/// 1.16.5 fields.
+#[derive(Encode, Debug)]
+pub struct PlayerPositionAndLookResponseV1_17 {
+    pub position: PlayerPositionAndLookResponse,
+    /// Takes the player off the vehicle they are riding.
+    pub dismount_vehicle: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use pandora_utils::types::encode_types::Encode;
+
+    use super::*;
+
+    const UUID: Uuid = Uuid::from_u128(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10);
+
+    fn uuid_bytes() -> Vec<u8> {
+        UUID.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn player_info_adds_players_with_their_properties() {
+        let packet = PlayerInfoResponse::AddPlayer(vec![PlayerInfoEntry {
+            uuid: UUID,
+            name: "Ab".to_string(),
+            properties: vec![ProfileProperty {
+                name: "t".to_string(),
+                value: "v".to_string(),
+                signature: Some("s".to_string()),
+            }],
+            gamemode: 1,
+            latency: 300,
+            display_name: None,
+        }]);
+
+        let mut expected = vec![0x00, 0x01];
+        expected.extend(uuid_bytes());
+        expected.extend([0x02, b'A', b'b']);
+        expected.extend([0x01, 0x01, b't', 0x01, b'v', 0x01, 0x01, b's']);
+        expected.extend([0x01, 0xAC, 0x02, 0x00]);
+        assert_eq!(packet.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn player_info_updates_latency_and_removes_players() {
+        let packet = PlayerInfoResponse::UpdateLatency(vec![PlayerLatency {
+            uuid: UUID,
+            latency: 20,
+        }]);
+        let mut expected = vec![0x02, 0x01];
+        expected.extend(uuid_bytes());
+        expected.push(20);
+        assert_eq!(packet.to_bytes().unwrap(), expected);
+
+        let packet = PlayerInfoResponse::RemovePlayer(vec![UUID]);
+        let mut expected = vec![0x04, 0x01];
+        expected.extend(uuid_bytes());
+        assert_eq!(packet.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn position_and_look_since_1_17_ends_with_the_dismount_flag() {
+        let position = PlayerPositionAndLookResponse {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            yaw: 90.0,
+            pitch: -45.0,
+            flags: (TeleportFlags::X | TeleportFlags::PITCH).0,
+            teleport_id: 300,
+        };
+        let mut expected = Vec::new();
+        expected.extend(1.0f64.to_be_bytes());
+        expected.extend(2.0f64.to_be_bytes());
+        expected.extend(3.0f64.to_be_bytes());
+        expected.extend(90.0f32.to_be_bytes());
+        expected.extend((-45.0f32).to_be_bytes());
+        expected.extend([0x11, 0xAC, 0x02]);
+        assert_eq!(position.to_bytes().unwrap(), expected);
+
+        let packet = PlayerPositionAndLookResponseV1_17 {
+            position,
+            dismount_vehicle: true,
+        };
+        expected.push(0x01);
+        assert_eq!(packet.to_bytes().unwrap(), expected);
+    }
+}
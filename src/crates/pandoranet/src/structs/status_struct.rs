@@ -0,0 +1,85 @@
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use log::debug;
+use packet_manager::ProtocolError;
+use pandora_macros::Decode;
+use pandora_utils::responses::send_status_response::{build_status_response, STATUS_SAMPLE_SIZE};
+
+use crate::handler::{PacketHandler, ServerContext};
+use crate::registry::{enabled_version_names, encode_packet};
+use crate::structs::protocol::{ClientHandshakeRequest, PongResponse};
+use crate::Connection;
+
+#[derive(Decode, Debug)]
+pub struct StatusRequestPacket {}
+
+pub struct StatusRequestHandler;
+
+#[async_trait]
+impl PacketHandler<StatusRequestPacket> for StatusRequestHandler {
+    async fn handle(
+        &self,
+        _packet: StatusRequestPacket,
+        conn: &mut Connection,
+        server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        debug!("Status request received");
+
+        let online = server.connections.connection_count.load(Ordering::Relaxed);
+        let sample = server.connections.player_sample(STATUS_SAMPLE_SIZE);
+        let response_data = build_status_response(
+            &enabled_version_names(),
+            conn.protocol_version,
+            online,
+            &sample,
+        );
+
+        let packet = ClientHandshakeRequest {
+            res_json: response_data.to_string(),
+        };
+
+        let data = encode_packet(conn.protocol_version, &packet).map_err(|err| {
+            log::error!("Failed to encode status response {:?}", err);
+            err
+        })?;
+
+        conn.push_to_queue(data).await;
+
+        Ok(())
+    }
+}
+
+#[derive(Decode, Debug)]
+#[packet(id = 0x01, state = Status, direction = Serverbound)]
+pub struct PingPacket {
+    pub payload: i64,
+}
+
+pub struct PingHandler;
+
+#[async_trait]
+impl PacketHandler<PingPacket> for PingHandler {
+    async fn handle(
+        &self,
+        packet: PingPacket,
+        conn: &mut Connection,
+        _server: &ServerContext,
+    ) -> Result<(), ProtocolError> {
+        debug!("Ping received with payload {}", packet.payload);
+
+        let packet = PongResponse {
+            payload: packet.payload,
+        };
+
+        let data = encode_packet(conn.protocol_version, &packet).map_err(|err| {
+            log::error!("Failed to encode pong {:?}", err);
+            err
+        })?;
+
+        conn.push_to_queue(data).await;
+        conn.close();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Drives connections over loopback sockets in tests.
+
+use std::{path::Path, sync::Once, time::Duration};
+
+use bytes::BytesMut;
+use packet_manager::{types::varint_types::VarInt, PacketCodec};
+use pandora_utils::{
+    config::Config,
+    types::{decode_types::Decode, encode_types::Encode},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, MutexGuard},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::encryption::Cfb8Cipher;
+use crate::{Connection, ConnectionHandle, DisconnectReason, CONNECTION_MANAGER};
+
+/// Protocol number of 1.16.5, the version the tests log in with.
+pub const PROTOCOL: i32 = 754;
+
+/// How long a test waits for the server before failing.
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TEST_CONFIG: &str = r#"{
+    "host": "127.0.0.1",
+    "port": 0,
+    "max_players": 20,
+    "motd": "A Pandora server",
+    "compression_threshold": -1
+}"#;
+
+static INIT: Once = Once::new();
+static CONFIG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Installs the test configuration and runs from the repository root, where
+/// the server finds `assets/`.
+pub fn init() {
+    INIT.call_once(|| {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../..");
+        std::env::set_current_dir(root).unwrap();
+        Config::set(test_config());
+    });
+}
+
+/// The configuration tests run with: offline mode without compression.
+pub fn test_config() -> Config {
+    Config::parse(TEST_CONFIG).unwrap()
+}
+
+/// Held by tests whose outcome depends on the configuration, such as every
+/// login, so that they never run while another test has changed it.
+pub async fn lock_config() -> MutexGuard<'static, ()> {
+    init();
+    CONFIG_LOCK.lock().await
+}
+
+/// Installs `config` until the returned guard is dropped, which restores the
+/// test configuration.
+pub async fn override_config(config: Config) -> ConfigOverride {
+    let lock = lock_config().await;
+    Config::set(config);
+    ConfigOverride { _lock: lock }
+}
+
+pub struct ConfigOverride {
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for ConfigOverride {
+    fn drop(&mut self) {
+        Config::set(test_config());
+    }
+}
+
+/// A client connected to a server-side `Connection` served on its own task.
+pub struct TestClient {
+    pub stream: TcpStream,
+    codec: PacketCodec,
+    buffer: BytesMut,
+    /// Encrypts what the client sends and decrypts what it receives.
+    ciphers: Option<(Cfb8Cipher, Cfb8Cipher)>,
+}
+
+/// A connection over loopback that nothing serves, for calling handlers
+/// directly. Returns it with the client end of its socket.
+pub async fn idle_connection() -> (Connection, TcpStream) {
+    init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+
+    (
+        Connection::new(socket, CONNECTION_MANAGER.generate_id()),
+        stream,
+    )
+}
+
+/// Connects a client to a new connection. Returns the connection's handle and
+/// the task serving it, which ends with the reason the connection ended.
+pub async fn connect() -> (TestClient, ConnectionHandle, JoinHandle<DisconnectReason>) {
+    let (mut connection, stream) = idle_connection().await;
+    let handle = connection.handle();
+    CONNECTION_MANAGER.add_connection(handle.clone());
+
+    let task = tokio::spawn(async move {
+        let reason = connection.start_connection().await;
+        CONNECTION_MANAGER.remove_connection(connection.id, &reason);
+        reason
+    });
+
+    let client = TestClient {
+        stream,
+        codec: PacketCodec::new(),
+        buffer: BytesMut::new(),
+        ciphers: None,
+    };
+    (client, handle, task)
+}
+
+impl TestClient {
+    /// Sends packet `packet_id` with the encoded `fields` as its data.
+    pub async fn send(&mut self, packet_id: i32, fields: &[&dyn EncodeField]) {
+        let mut packet = Vec::new();
+        VarInt::new(packet_id).encode(&mut packet).unwrap();
+        for field in fields {
+            field.encode_field(&mut packet);
+        }
+
+        let mut frame = BytesMut::new();
+        self.codec.encode(&packet[..], &mut frame).unwrap();
+        if let Some((encrypt, _)) = &mut self.ciphers {
+            encrypt.encrypt(&mut frame);
+        }
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    /// Encrypts everything sent and received from here on, as the client does
+    /// right after sending Encryption Response.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) {
+        self.ciphers = Some((
+            Cfb8Cipher::new(shared_secret).unwrap(),
+            Cfb8Cipher::new(shared_secret).unwrap(),
+        ));
+    }
+
+    /// Sends a Handshake moving to `next_state`.
+    pub async fn handshake(&mut self, next_state: i32) {
+        self.handshake_with(PROTOCOL, next_state).await;
+    }
+
+    /// Sends a Handshake for `protocol` moving to `next_state`.
+    pub async fn handshake_with(&mut self, protocol: i32, next_state: i32) {
+        self.send(
+            0x00,
+            &[
+                &VarInt::new(protocol),
+                &"localhost".to_string(),
+                &25565u16,
+                &VarInt::new(next_state),
+            ],
+        )
+        .await;
+    }
+
+    /// Waits for the next packet, returning its id and data.
+    pub async fn receive(&mut self) -> (i32, Vec<u8>) {
+        timeout(TEST_TIMEOUT, async {
+            loop {
+                if let Some(frame) = self.codec.decode(&mut self.buffer).unwrap() {
+                    let mut data = &frame[..];
+                    let packet_id = VarInt::decode(&mut data).unwrap().to_i32();
+                    return (packet_id, data.to_vec());
+                }
+
+                let start = self.buffer.len();
+                let read = self.stream.read_buf(&mut self.buffer).await.unwrap();
+                assert_ne!(read, 0, "connection closed while waiting for a packet");
+                if let Some((_, decrypt)) = &mut self.ciphers {
+                    decrypt.decrypt(&mut self.buffer[start..]);
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for a packet")
+    }
+
+    /// Reads everything the server writes until it closes the connection.
+    pub async fn read_to_end(&mut self) -> Vec<u8> {
+        let mut data = self.buffer.split().to_vec();
+        let start = data.len();
+        timeout(TEST_TIMEOUT, self.stream.read_to_end(&mut data))
+            .await
+            .expect("timed out waiting for the connection to close")
+            .unwrap();
+        if let Some((_, decrypt)) = &mut self.ciphers {
+            decrypt.decrypt(&mut data[start..]);
+        }
+        data
+    }
+}
+
+/// A packet field the test client can send, see `TestClient::send`.
+pub trait EncodeField {
+    fn encode_field(&self, packet: &mut Vec<u8>);
+}
+
+impl<T: Encode> EncodeField for T {
+    fn encode_field(&self, packet: &mut Vec<u8>) {
+        self.encode(packet).unwrap();
+    }
+}
+
+/// Waits for the task serving a connection to end.
+pub async fn finished(task: JoinHandle<DisconnectReason>) -> DisconnectReason {
+    timeout(TEST_TIMEOUT, task)
+        .await
+        .expect("timed out waiting for the connection to end")
+        .unwrap()
+}
+
+/// Serves a single request on a local port, answering with `response`, a full
+/// HTTP response. Returns the server's URL and the request line it received.
+pub async fn mock_http_server(response: String) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let request = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+            let mut chunk = [0u8; 1024];
+            let read = socket.read(&mut chunk).await.unwrap();
+            assert_ne!(read, 0, "request ended early");
+            request.extend_from_slice(&chunk[..read]);
+        }
+        socket.write_all(response.as_bytes()).await.unwrap();
+
+        let request = String::from_utf8(request).unwrap();
+        request.lines().next().unwrap_or_default().to_string()
+    });
+
+    (url, request)
+}
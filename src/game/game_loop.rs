@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+use pandora_utils::tick_stats::{TICK_DURATION, TICK_STATS};
+use pandoranet::actions::PlayerAction;
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::game::{outbox::Outbox, world::World};
+
+/// How far the loop may fall behind schedule before it gives up on the missed
+/// ticks. Until then it runs ticks back to back to catch up.
+const MAX_CATCH_UP: Duration = Duration::from_secs(2);
+/// Minimum time between two warnings about falling behind.
+const BEHIND_WARNING_INTERVAL: Duration = Duration::from_secs(15);
+/// How often the tick statistics are logged, once a minute at full speed.
+const STATS_LOG_INTERVAL: i64 = 1200;
+
+/// When the next tick is due. Ticks are `TICK_DURATION` apart, and ticks that
+/// are late run back to back until the loop is on schedule again.
+struct Schedule {
+    next_tick: Instant,
+}
+
+impl Schedule {
+    fn new(start: Instant) -> Self {
+        Self { next_tick: start }
+    }
+
+    /// Schedules the tick after one that ended at `now`. When the loop is more
+    /// than `MAX_CATCH_UP` behind, the missed ticks are skipped and this returns
+    /// how far behind it was.
+    fn advance(&mut self, now: Instant) -> Option<Duration> {
+        self.next_tick += TICK_DURATION;
+        let behind = now.saturating_duration_since(self.next_tick);
+
+        if behind > MAX_CATCH_UP {
+            self.next_tick = now + TICK_DURATION;
+            Some(behind)
+        } else {
+            None
+        }
+    }
+}
+
+/// Runs the world at `TICKS_PER_SECOND`. Every tick applies the actions sent by
+/// connections since the last one, advances the world and then writes the
+/// packets it produced, one batch per player.
+pub struct GameLoop {
+    pub world: World,
+    actions: mpsc::UnboundedReceiver<PlayerAction>,
+    outbox: Outbox,
+}
+
+impl GameLoop {
+    pub fn new(world: World, actions: mpsc::UnboundedReceiver<PlayerAction>) -> Self {
+        Self {
+            world,
+            actions,
+            outbox: Outbox::new(),
+        }
+    }
+
+    /// Ticks until `shutdown` is cancelled, recording every tick in `TICK_STATS`.
+    pub async fn run(mut self, shutdown: CancellationToken) {
+        let mut schedule = Schedule::new(Instant::now());
+        let mut last_warning: Option<Instant> = None;
+
+        loop {
+            tokio::select! {
+                _ = sleep_until(schedule.next_tick) => {}
+                _ = shutdown.cancelled() => break,
+            }
+
+            let start = Instant::now();
+            self.tick();
+            let duration = start.elapsed();
+            TICK_STATS.record(start, duration);
+
+            if duration > TICK_DURATION {
+                debug!("Tick {} took {:?}", self.world.age, duration);
+            }
+
+            if self.world.age % STATS_LOG_INTERVAL == 0 {
+                let report = TICK_STATS.report();
+                debug!(
+                    "{:.1} TPS, {:.2} mspt on average and {:.2} at most",
+                    report.tps, report.mspt, report.max_mspt
+                );
+            }
+
+            let now = Instant::now();
+            if let Some(behind) = schedule.advance(now) {
+                if last_warning.is_none_or(|warned| now - warned >= BEHIND_WARNING_INTERVAL) {
+                    warn!(
+                        "Can't keep up! Running {}ms or {} ticks behind, skipping them",
+                        behind.as_millis(),
+                        behind.as_millis() / TICK_DURATION.as_millis()
+                    );
+                    last_warning = Some(now);
+                }
+            }
+        }
+
+        debug!("Game loop stopped after {} ticks", self.world.age);
+    }
+
+    /// Runs one tick right away.
+    pub fn tick(&mut self) {
+        // Only the actions queued so far, so connections that keep sending
+        // cannot hold up the tick.
+        for _ in 0..self.actions.len() {
+            match self.actions.try_recv() {
+                Ok(action) => self.world.apply(action, &mut self.outbox),
+                Err(_) => break,
+            }
+        }
+
+        self.world.tick(&mut self.outbox);
+        self.outbox.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pandoranet::structs::login_struct::offline_uuid;
+
+    use super::*;
+    use crate::game::{
+        player::Location,
+        test_util::{batches, init, online_player, PROTOCOL},
+    };
+
+    const SPAWN: Location = Location {
+        x: 0.5,
+        y: 64.0,
+        z: 0.5,
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn ticks_every_50ms() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new(start);
+
+        assert_eq!(schedule.advance(start + ms(5)), None);
+        assert_eq!(schedule.next_tick, start + ms(50));
+        assert_eq!(schedule.advance(start + ms(60)), None);
+        assert_eq!(schedule.next_tick, start + ms(100));
+    }
+
+    #[test]
+    fn catches_up_after_a_slow_tick() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new(start);
+
+        // The first tick takes 170ms, so the next three are already due and
+        // run back to back before the loop waits again.
+        let end = start + ms(170);
+        assert_eq!(schedule.advance(end), None);
+        assert_eq!(schedule.next_tick, start + ms(50));
+        assert_eq!(schedule.advance(end + ms(1)), None);
+        assert_eq!(schedule.next_tick, start + ms(100));
+        assert_eq!(schedule.advance(end + ms(2)), None);
+        assert_eq!(schedule.next_tick, start + ms(150));
+        assert_eq!(schedule.advance(end + ms(3)), None);
+        assert_eq!(schedule.next_tick, start + ms(200));
+    }
+
+    #[test]
+    fn skips_ticks_when_more_than_2s_behind() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new(start);
+
+        // Exactly 2s behind still catches up.
+        assert_eq!(schedule.advance(start + ms(2050)), None);
+        assert_eq!(schedule.next_tick, start + ms(50));
+
+        let mut schedule = Schedule::new(start);
+        let end = start + ms(2500);
+        assert_eq!(schedule.advance(end), Some(ms(2450)));
+        assert_eq!(schedule.next_tick, end + TICK_DURATION);
+    }
+
+    /// The world age of every Time Update in `batch`.
+    fn time_updates(batch: &[(i32, Vec<u8>)]) -> Vec<i64> {
+        batch
+            .iter()
+            .filter(|(id, _)| *id == 0x4e)
+            .map(|(_, data)| i64::from_be_bytes(data[..8].try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn applies_actions_then_ticks_the_world_then_flushes() {
+        init();
+        let (actions_sender, actions) = mpsc::unbounded_channel();
+        let mut game_loop = GameLoop::new(World::new(SPAWN), actions);
+        // The world sends the time on its next tick.
+        game_loop.world.age = 19;
+
+        let (player, mut queue) = online_player("Tester", 1, PROTOCOL);
+        let uuid = offline_uuid("Tester");
+        actions_sender
+            .send(PlayerAction::Joined { uuid, player })
+            .unwrap();
+        assert!(batches(&mut queue).is_empty());
+
+        game_loop.tick();
+
+        // Everything from the tick goes out as one batch: the time and position
+        // sent on joining at age 19, then the time broadcast by the world tick.
+        let sent = batches(&mut queue);
+        assert_eq!(sent.len(), 1);
+        let ids: Vec<i32> = sent[0].iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [0x4e, 0x34, 0x32, 0x4e]);
+        assert_eq!(time_updates(&sent[0]), [19, 20]);
+        assert_eq!(game_loop.world.age, 20);
+        assert!(game_loop.world.players.contains_key(&uuid));
+    }
+}
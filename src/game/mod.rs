@@ -0,0 +1,6 @@
+pub mod game_loop;
+pub mod outbox;
+pub mod player;
+#[cfg(test)]
+mod test_util;
+pub mod world;
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use packet_manager::ProtocolError;
+use pandora_utils::types::{encode_types::Encode, packet_types::Packet};
+use pandoranet::{registry::encode_packet, OnlinePlayer};
+
+/// Packets queued during a tick, written to each player in one batch when the
+/// tick ends.
+#[derive(Default)]
+pub struct Outbox {
+    /// Queued packets by connection id.
+    batches: HashMap<u32, (OnlinePlayer, Vec<Vec<u8>>)>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send<P: Packet + Encode>(
+        &mut self,
+        player: &OnlinePlayer,
+        packet: &P,
+    ) -> Result<(), ProtocolError> {
+        let data = encode_packet(player.protocol_version, packet)?;
+        self.queue(player, data);
+        Ok(())
+    }
+
+    /// Sends `packet` to every player in `players`, encoding it once per protocol
+    /// version.
+    pub fn broadcast<'a, P: Packet + Encode>(
+        &mut self,
+        players: impl IntoIterator<Item = &'a OnlinePlayer>,
+        packet: &P,
+    ) -> Result<(), ProtocolError> {
+        let mut encoded: HashMap<i32, Vec<u8>> = HashMap::new();
+
+        for player in players {
+            let data = match encoded.get(&player.protocol_version) {
+                Some(data) => data.clone(),
+                None => {
+                    let data = encode_packet(player.protocol_version, packet)?;
+                    encoded.insert(player.protocol_version, data.clone());
+                    data
+                }
+            };
+            self.queue(player, data);
+        }
+
+        Ok(())
+    }
+
+    fn queue(&mut self, player: &OnlinePlayer, data: Vec<u8>) {
+        self.batches
+            .entry(player.connection_id)
+            .or_insert_with(|| (player.clone(), Vec::new()))
+            .1
+            .push(data);
+    }
+
+    /// Hands every player's packets to their writer as one batch.
+    pub fn flush(&mut self) {
+        for (_, (player, packets)) in self.batches.drain() {
+            player.send_batch(packets);
+        }
+    }
+}
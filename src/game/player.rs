@@ -0,0 +1,513 @@
+use log::{debug, warn};
+use packet_manager::ProtocolError;
+use pandora_utils::{
+    config::{GameMode, SpawnPoint},
+    types::packet_types::Packet,
+};
+use pandoranet::{
+    actions::Movement,
+    structs::protocol::{
+        PlayerPositionAndLookResponse, PlayerPositionAndLookResponseV1_17, TeleportFlags,
+    },
+    OnlinePlayer,
+};
+
+use crate::game::outbox::Outbox;
+
+/// Furthest a player may move with one packet, squared. Longer moves are undone,
+/// the vanilla limit for players that are not gliding.
+const MAX_MOVE_DISTANCE_SQUARED: f64 = 100.0;
+/// Ticks to wait for Teleport Confirm before sending the teleport again.
+const TELEPORT_RESEND_TICKS: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Location {
+    pub fn distance_squared(&self, other: &Location) -> f64 {
+        (self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2)
+    }
+}
+
+impl From<SpawnPoint> for Location {
+    fn from(spawn: SpawnPoint) -> Self {
+        Location {
+            x: spawn.x,
+            y: spawn.y,
+            z: spawn.z,
+            yaw: spawn.yaw,
+            pitch: spawn.pitch,
+        }
+    }
+}
+
+/// Where to teleport a player. Fields named in `relative` are added to the
+/// player's current location instead of replacing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Teleport {
+    pub location: Location,
+    pub relative: TeleportFlags,
+}
+
+impl Teleport {
+    pub fn to(location: Location) -> Self {
+        Self {
+            location,
+            relative: TeleportFlags::ABSOLUTE,
+        }
+    }
+
+    /// The location a player at `from` ends up at.
+    pub fn resolve(&self, from: &Location) -> Location {
+        let relative = self.relative;
+        let to = self.location;
+
+        Location {
+            x: if relative.contains(TeleportFlags::X) {
+                from.x + to.x
+            } else {
+                to.x
+            },
+            y: if relative.contains(TeleportFlags::Y) {
+                from.y + to.y
+            } else {
+                to.y
+            },
+            z: if relative.contains(TeleportFlags::Z) {
+                from.z + to.z
+            } else {
+                to.z
+            },
+            yaw: if relative.contains(TeleportFlags::YAW) {
+                from.yaw + to.yaw
+            } else {
+                to.yaw
+            },
+            pitch: if relative.contains(TeleportFlags::PITCH) {
+                from.pitch + to.pitch
+            } else {
+                to.pitch
+            },
+        }
+    }
+}
+
+/// A teleport the client has not confirmed yet.
+#[derive(Debug, Clone, Copy)]
+struct PendingTeleport {
+    id: i32,
+    /// World age when the teleport was sent.
+    sent_at: i64,
+}
+
+/// A player in the world.
+pub struct Player {
+    pub connection: OnlinePlayer,
+    /// Where the server has the player, the last accepted movement or teleport.
+    pub location: Location,
+    pub on_ground: bool,
+    /// The game mode the player was sent in Join Game.
+    pub gamemode: GameMode,
+    next_teleport_id: i32,
+    pending_teleport: Option<PendingTeleport>,
+}
+
+impl Player {
+    pub fn new(connection: OnlinePlayer, location: Location, gamemode: GameMode) -> Self {
+        Self {
+            connection,
+            location,
+            on_ground: false,
+            gamemode,
+            next_teleport_id: 1,
+            pending_teleport: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.connection.name
+    }
+
+    /// Sends the player to `teleport` and returns the teleport's id. Movement
+    /// from the client is ignored until it confirms the teleport.
+    pub fn teleport(
+        &mut self,
+        teleport: Teleport,
+        tick: i64,
+        outbox: &mut Outbox,
+    ) -> Result<i32, ProtocolError> {
+        let id = self.next_teleport_id;
+
+        let packet = PlayerPositionAndLookResponse {
+            x: teleport.location.x,
+            y: teleport.location.y,
+            z: teleport.location.z,
+            yaw: teleport.location.yaw,
+            pitch: teleport.location.pitch,
+            flags: teleport.relative.0,
+            teleport_id: id,
+        };
+        if PlayerPositionAndLookResponseV1_17::id(self.connection.protocol_version).is_some() {
+            let packet = PlayerPositionAndLookResponseV1_17 {
+                position: packet,
+                dismount_vehicle: false,
+            };
+            outbox.send(&self.connection, &packet)?;
+        } else {
+            outbox.send(&self.connection, &packet)?;
+        }
+
+        self.next_teleport_id = id.wrapping_add(1);
+        self.location = teleport.resolve(&self.location);
+        self.pending_teleport = Some(PendingTeleport { id, sent_at: tick });
+
+        Ok(id)
+    }
+
+    /// Whether a teleport is waiting for Teleport Confirm.
+    pub fn is_teleporting(&self) -> bool {
+        self.pending_teleport.is_some()
+    }
+
+    /// Accepts Teleport Confirm for the latest teleport. Returns `false` for
+    /// any other id.
+    pub fn confirm_teleport(&mut self, id: i32) -> bool {
+        match self.pending_teleport {
+            Some(pending) if pending.id == id => {
+                self.pending_teleport = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Applies movement reported by the client. Movement during a teleport is
+    /// ignored, and moves that are too long are undone by teleporting the
+    /// player back.
+    pub fn apply_movement(
+        &mut self,
+        movement: &Movement,
+        tick: i64,
+        outbox: &mut Outbox,
+    ) -> Result<(), ProtocolError> {
+        if self.is_teleporting() {
+            return Ok(());
+        }
+
+        let mut location = self.location;
+        if let Some((x, y, z)) = movement.position {
+            location.x = x;
+            location.y = y;
+            location.z = z;
+        }
+        if let Some((yaw, pitch)) = movement.rotation {
+            location.yaw = yaw;
+            location.pitch = pitch;
+        }
+
+        if location.distance_squared(&self.location) > MAX_MOVE_DISTANCE_SQUARED {
+            warn!("{} moved too quickly!", self.name());
+            self.teleport(Teleport::to(self.location), tick, outbox)?;
+            return Ok(());
+        }
+
+        self.location = location;
+        self.on_ground = movement.on_ground;
+        Ok(())
+    }
+
+    /// Sends the current teleport again if the client has not confirmed it in
+    /// time, replacing its id.
+    pub fn tick(&mut self, tick: i64, outbox: &mut Outbox) -> Result<(), ProtocolError> {
+        if let Some(pending) = self.pending_teleport {
+            if tick - pending.sent_at >= TELEPORT_RESEND_TICKS {
+                debug!("Resending teleport {} to {}", pending.id, self.name());
+                self.teleport(Teleport::to(self.location), tick, outbox)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packet_manager::types::varint_types::VarInt;
+    use pandora_utils::types::decode_types::Decode;
+    use pandoranet::Outgoing;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::game::test_util::{batches, online_player, PROTOCOL};
+
+    const SPAWN: Location = Location {
+        x: 0.5,
+        y: 64.0,
+        z: 0.5,
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+
+    /// A Player Position And Look as the client reads it.
+    #[derive(Debug, PartialEq)]
+    struct Sent {
+        location: Location,
+        flags: u8,
+        teleport_id: i32,
+    }
+
+    fn player(protocol: i32) -> (Player, mpsc::Receiver<Outgoing>) {
+        let (connection, queue) = online_player("Tester", 1, protocol);
+        (Player::new(connection, SPAWN, GameMode::Survival), queue)
+    }
+
+    /// A player at the spawn that confirmed its spawn teleport.
+    fn spawned() -> (Player, Outbox, mpsc::Receiver<Outgoing>) {
+        let (mut player, mut queue) = player(PROTOCOL);
+        let mut outbox = Outbox::new();
+        let id = player
+            .teleport(Teleport::to(SPAWN), 0, &mut outbox)
+            .unwrap();
+        assert!(player.confirm_teleport(id));
+        teleports(&mut outbox, &mut queue);
+        (player, outbox, queue)
+    }
+
+    /// Flushes `outbox` and returns the teleports it sent.
+    fn teleports(outbox: &mut Outbox, queue: &mut mpsc::Receiver<Outgoing>) -> Vec<Sent> {
+        outbox.flush();
+        batches(queue)
+            .into_iter()
+            .flatten()
+            .map(|(id, data)| {
+                assert_eq!(id, 0x34);
+                let mut data = &data[..];
+                let sent = Sent {
+                    location: Location {
+                        x: f64::decode(&mut data).unwrap(),
+                        y: f64::decode(&mut data).unwrap(),
+                        z: f64::decode(&mut data).unwrap(),
+                        yaw: f32::decode(&mut data).unwrap(),
+                        pitch: f32::decode(&mut data).unwrap(),
+                    },
+                    flags: u8::decode(&mut data).unwrap(),
+                    teleport_id: VarInt::decode(&mut data).unwrap().to_i32(),
+                };
+                assert!(data.is_empty());
+                sent
+            })
+            .collect()
+    }
+
+    fn walk_to(x: f64, y: f64, z: f64) -> Movement {
+        Movement {
+            position: Some((x, y, z)),
+            rotation: None,
+            on_ground: true,
+        }
+    }
+
+    #[test]
+    fn resolves_relative_teleports_against_the_current_location() {
+        let from = Location {
+            x: 10.0,
+            y: 70.0,
+            z: -10.0,
+            yaw: 90.0,
+            pitch: 10.0,
+        };
+        let by = Location {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            yaw: 4.0,
+            pitch: 5.0,
+        };
+
+        assert_eq!(Teleport::to(by).resolve(&from), by);
+
+        let teleport = Teleport {
+            location: by,
+            relative: TeleportFlags::X | TeleportFlags::YAW,
+        };
+        assert_eq!(
+            teleport.resolve(&from),
+            Location {
+                x: 11.0,
+                y: 2.0,
+                z: 3.0,
+                yaw: 94.0,
+                pitch: 5.0,
+            }
+        );
+
+        let teleport = Teleport {
+            location: by,
+            relative: TeleportFlags::X
+                | TeleportFlags::Y
+                | TeleportFlags::Z
+                | TeleportFlags::YAW
+                | TeleportFlags::PITCH,
+        };
+        assert_eq!(
+            teleport.resolve(&from),
+            Location {
+                x: 11.0,
+                y: 72.0,
+                z: -7.0,
+                yaw: 94.0,
+                pitch: 15.0,
+            }
+        );
+    }
+
+    #[test]
+    fn sends_relative_teleports_with_their_flags() {
+        let (mut player, mut outbox, mut queue) = spawned();
+        let teleport = Teleport {
+            location: Location { y: 5.0, ..SPAWN },
+            relative: TeleportFlags::Y,
+        };
+        player.teleport(teleport, 1, &mut outbox).unwrap();
+
+        assert_eq!(player.location, Location { y: 69.0, ..SPAWN });
+        assert_eq!(
+            teleports(&mut outbox, &mut queue),
+            [Sent {
+                location: Location { y: 5.0, ..SPAWN },
+                flags: TeleportFlags::Y.0,
+                teleport_id: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn confirms_only_the_latest_teleport() {
+        let (mut player, mut queue) = player(PROTOCOL);
+        let mut outbox = Outbox::new();
+
+        let first = player
+            .teleport(Teleport::to(SPAWN), 0, &mut outbox)
+            .unwrap();
+        let second = player
+            .teleport(Teleport::to(SPAWN), 0, &mut outbox)
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+        let ids: Vec<i32> = teleports(&mut outbox, &mut queue)
+            .iter()
+            .map(|sent| sent.teleport_id)
+            .collect();
+        assert_eq!(ids, [1, 2]);
+
+        assert!(!player.confirm_teleport(first), "stale id");
+        assert!(!player.confirm_teleport(7), "unknown id");
+        assert!(player.is_teleporting());
+
+        assert!(player.confirm_teleport(second));
+        assert!(!player.is_teleporting());
+        assert!(!player.confirm_teleport(second), "already confirmed");
+    }
+
+    #[test]
+    fn ignores_movement_until_the_teleport_is_confirmed() {
+        let (mut player, mut queue) = player(PROTOCOL);
+        let mut outbox = Outbox::new();
+        let id = player
+            .teleport(Teleport::to(SPAWN), 0, &mut outbox)
+            .unwrap();
+        teleports(&mut outbox, &mut queue);
+
+        player
+            .apply_movement(&walk_to(1.5, 64.0, 0.5), 1, &mut outbox)
+            .unwrap();
+        assert_eq!(player.location, SPAWN);
+        assert!(!player.on_ground);
+
+        assert!(player.confirm_teleport(id));
+        player
+            .apply_movement(&walk_to(1.5, 64.0, 0.5), 2, &mut outbox)
+            .unwrap();
+        assert_eq!(player.location, Location { x: 1.5, ..SPAWN });
+        assert!(player.on_ground);
+        assert!(teleports(&mut outbox, &mut queue).is_empty());
+    }
+
+    #[test]
+    fn rolls_back_moves_that_are_too_long() {
+        let (mut player, mut outbox, mut queue) = spawned();
+
+        // Ten blocks is still allowed.
+        player
+            .apply_movement(&walk_to(10.5, 64.0, 0.5), 1, &mut outbox)
+            .unwrap();
+        assert_eq!(player.location.x, 10.5);
+        assert!(teleports(&mut outbox, &mut queue).is_empty());
+
+        player
+            .apply_movement(&walk_to(10.5, 64.0, 10.6), 2, &mut outbox)
+            .unwrap();
+        let back = Location { x: 10.5, ..SPAWN };
+        assert_eq!(player.location, back);
+        assert!(player.is_teleporting());
+        assert_eq!(
+            teleports(&mut outbox, &mut queue),
+            [Sent {
+                location: back,
+                flags: 0,
+                teleport_id: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn resends_unconfirmed_teleports_after_20_ticks() {
+        let (mut player, mut queue) = player(PROTOCOL);
+        let mut outbox = Outbox::new();
+        player
+            .teleport(Teleport::to(SPAWN), 100, &mut outbox)
+            .unwrap();
+        teleports(&mut outbox, &mut queue);
+
+        player.tick(119, &mut outbox).unwrap();
+        assert!(teleports(&mut outbox, &mut queue).is_empty());
+
+        player.tick(120, &mut outbox).unwrap();
+        assert_eq!(
+            teleports(&mut outbox, &mut queue),
+            [Sent {
+                location: SPAWN,
+                flags: 0,
+                teleport_id: 2,
+            }]
+        );
+        assert!(!player.confirm_teleport(1));
+        assert!(player.confirm_teleport(2));
+
+        player.tick(200, &mut outbox).unwrap();
+        assert!(teleports(&mut outbox, &mut queue).is_empty());
+    }
+
+    #[test]
+    fn sends_1_17_players_the_dismount_flag() {
+        let (mut player, mut queue) = player(756);
+        let mut outbox = Outbox::new();
+        player
+            .teleport(Teleport::to(SPAWN), 0, &mut outbox)
+            .unwrap();
+        outbox.flush();
+
+        let sent = batches(&mut queue).concat();
+        assert_eq!(sent.len(), 1);
+        let (id, data) = &sent[0];
+        assert_eq!(*id, 0x38);
+        // The 1.16.5 fields, a one byte teleport id and dismount_vehicle.
+        assert_eq!(data.len(), 3 * 8 + 2 * 4 + 1 + 1 + 1);
+        assert_eq!(data[data.len() - 2..], [0x01, 0x00]);
+    }
+}
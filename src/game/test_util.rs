@@ -0,0 +1,69 @@
+//! Players with in-memory connections, for testing the game loop and world.
+
+use std::sync::{Arc, Once};
+
+use packet_manager::types::varint_types::VarInt;
+use pandora_utils::{config::Config, types::decode_types::Decode};
+use pandoranet::{keep_alive::Latency, OnlinePlayer, Outgoing};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Protocol number of 1.16.5.
+pub const PROTOCOL: i32 = 754;
+
+const TEST_CONFIG: &str = r#"{
+    "host": "127.0.0.1",
+    "port": 0,
+    "max_players": 20,
+    "motd": "A Pandora server",
+    "compression_threshold": -1
+}"#;
+
+static INIT: Once = Once::new();
+
+/// Installs the test configuration instead of reading `config.json`.
+pub fn init() {
+    INIT.call_once(|| Config::set(Config::parse(TEST_CONFIG).unwrap()));
+}
+
+/// A player on `protocol` whose packets end up in the returned queue.
+pub fn online_player(
+    name: &str,
+    connection_id: u32,
+    protocol: i32,
+) -> (OnlinePlayer, mpsc::Receiver<Outgoing>) {
+    let (send_queue_sender, queue) = mpsc::channel(64);
+    let player = OnlinePlayer {
+        name: name.to_string(),
+        connection_id,
+        protocol_version: protocol,
+        send_queue_sender,
+        shutdown: CancellationToken::new(),
+        latency: Latency::default(),
+        properties: Arc::from([]),
+    };
+    (player, queue)
+}
+
+/// Takes the batches queued for a player so far, each as the ids and data of
+/// its packets.
+pub fn batches(queue: &mut mpsc::Receiver<Outgoing>) -> Vec<Vec<(i32, Vec<u8>)>> {
+    let mut batches = Vec::new();
+
+    while let Ok(outgoing) = queue.try_recv() {
+        let Outgoing::Batch(packets) = outgoing else {
+            panic!("expected a batch, got {:?}", outgoing);
+        };
+        let packets = packets
+            .iter()
+            .map(|packet| {
+                let mut data = &packet[..];
+                let id = VarInt::decode(&mut data).unwrap().to_i32();
+                (id, data.to_vec())
+            })
+            .collect();
+        batches.push(packets);
+    }
+
+    batches
+}
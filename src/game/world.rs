@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use log::{debug, error};
+use pandora_utils::{config::Config, types::packet_types::Packet};
+use pandoranet::{
+    actions::PlayerAction,
+    structs::protocol::{
+        JoinGameResponse, PlayerInfoEntry, PlayerInfoResponse, PlayerLatency, TimeUpdateResponse,
+    },
+};
+use uuid::Uuid;
+
+use crate::game::{
+    outbox::Outbox,
+    player::{Location, Player, Teleport},
+};
+
+/// Length of a Minecraft day in ticks.
+pub const DAY_LENGTH: i64 = 24000;
+/// How often clients are sent the time, they advance it themselves in between.
+const TIME_UPDATE_INTERVAL: i64 = 20;
+/// How often the tab list latencies are updated, as in vanilla.
+const LATENCY_UPDATE_INTERVAL: i64 = 600;
+
+/// The game state, owned and advanced by the game loop.
+pub struct World {
+    /// Ticks since the world was created.
+    pub age: i64,
+    /// Ticks since the start of the day, 0 is sunrise.
+    pub time_of_day: i64,
+    /// Where players appear when they join.
+    pub spawn: Location,
+    /// Players that were sent Join Game.
+    pub players: HashMap<Uuid, Player>,
+}
+
+impl World {
+    pub fn new(spawn: Location) -> Self {
+        Self {
+            age: 0,
+            time_of_day: 0,
+            spawn,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Applies an action received from a connection since the last tick.
+    pub fn apply(&mut self, action: PlayerAction, outbox: &mut Outbox) {
+        match action {
+            PlayerAction::Joined { uuid, player } => {
+                // Versions without Join Game stay in the loading screen, so
+                // they are kept out of the world and sent nothing.
+                if JoinGameResponse::id(player.protocol_version).is_none() {
+                    debug!(
+                        "{} stays in the loading screen on protocol {}",
+                        player.name, player.protocol_version
+                    );
+                    return;
+                }
+
+                debug!("{} joined the world", player.name);
+                if let Err(e) = outbox.send(&player, &self.time_update()) {
+                    error!("Failed to encode time update {:?}", e);
+                }
+
+                let mut player = Player::new(player, self.spawn, Config::get().gamemode);
+                if let Err(e) = player.teleport(Teleport::to(self.spawn), self.age, outbox) {
+                    error!("Failed to encode player position and look {:?}", e);
+                }
+
+                // An older session of the player is being kicked, so it is
+                // left out of the tab list.
+                let others: Vec<(&Uuid, &Player)> = self
+                    .players
+                    .iter()
+                    .filter(|(other, _)| **other != uuid)
+                    .collect();
+                let mut entries: Vec<PlayerInfoEntry> = others
+                    .iter()
+                    .map(|(uuid, player)| player_info(**uuid, player))
+                    .collect();
+                entries.push(player_info(uuid, &player));
+
+                let joined = PlayerInfoResponse::AddPlayer(vec![player_info(uuid, &player)]);
+                let others = others.into_iter().map(|(_, other)| &other.connection);
+                if let Err(e) = outbox.broadcast(others, &joined) {
+                    error!("Failed to encode player info {:?}", e);
+                }
+                let everyone = PlayerInfoResponse::AddPlayer(entries);
+                if let Err(e) = outbox.send(&player.connection, &everyone) {
+                    error!("Failed to encode player info {:?}", e);
+                }
+
+                self.players.insert(uuid, player);
+            }
+            PlayerAction::Left {
+                uuid,
+                connection_id,
+            } => {
+                // A player that logged in again has a new connection by now.
+                if self.player_mut(uuid, connection_id).is_some() {
+                    if let Some(player) = self.players.remove(&uuid) {
+                        debug!("{} left the world", player.name());
+                    }
+
+                    let left = PlayerInfoResponse::RemovePlayer(vec![uuid]);
+                    let players = self.players.values().map(|player| &player.connection);
+                    if let Err(e) = outbox.broadcast(players, &left) {
+                        error!("Failed to encode player info {:?}", e);
+                    }
+                }
+            }
+            PlayerAction::TeleportConfirmed {
+                uuid,
+                connection_id,
+                teleport_id,
+            } => {
+                if let Some(player) = self.player_mut(uuid, connection_id) {
+                    if !player.confirm_teleport(teleport_id) {
+                        debug!(
+                            "Ignoring confirmation of teleport {} from {}",
+                            teleport_id,
+                            player.name()
+                        );
+                    }
+                }
+            }
+            PlayerAction::Moved {
+                uuid,
+                connection_id,
+                movement,
+            } => {
+                let age = self.age;
+                if let Some(player) = self.player_mut(uuid, connection_id) {
+                    if let Err(e) = player.apply_movement(&movement, age, outbox) {
+                        error!("Failed to encode player position and look {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The player `uuid` if it is still on connection `connection_id`.
+    fn player_mut(&mut self, uuid: Uuid, connection_id: u32) -> Option<&mut Player> {
+        self.players
+            .get_mut(&uuid)
+            .filter(|player| player.connection.connection_id == connection_id)
+    }
+
+    /// Advances the world by one tick.
+    pub fn tick(&mut self, outbox: &mut Outbox) {
+        self.age += 1;
+        self.time_of_day = (self.time_of_day + 1) % DAY_LENGTH;
+
+        if self.age % TIME_UPDATE_INTERVAL == 0 {
+            let time_update = self.time_update();
+            let players = self.players.values().map(|player| &player.connection);
+            if let Err(e) = outbox.broadcast(players, &time_update) {
+                error!("Failed to encode time update {:?}", e);
+            }
+        }
+
+        if self.age % LATENCY_UPDATE_INTERVAL == 0 && !self.players.is_empty() {
+            let latencies = self
+                .players
+                .iter()
+                .map(|(uuid, player)| PlayerLatency {
+                    uuid: *uuid,
+                    latency: latency_millis(player),
+                })
+                .collect();
+            let update = PlayerInfoResponse::UpdateLatency(latencies);
+            let players = self.players.values().map(|player| &player.connection);
+            if let Err(e) = outbox.broadcast(players, &update) {
+                error!("Failed to encode player info {:?}", e);
+            }
+        }
+
+        for player in self.players.values_mut() {
+            if let Err(e) = player.tick(self.age, outbox) {
+                error!("Failed to encode player position and look {:?}", e);
+            }
+        }
+    }
+
+    fn time_update(&self) -> TimeUpdateResponse {
+        TimeUpdateResponse {
+            world_age: self.age,
+            time_of_day: self.time_of_day,
+        }
+    }
+}
+
+/// The tab list entry of `player`.
+fn player_info(uuid: Uuid, player: &Player) -> PlayerInfoEntry {
+    PlayerInfoEntry {
+        uuid,
+        name: player.name().to_string(),
+        properties: player.connection.properties.to_vec(),
+        gamemode: player.gamemode.id() as i32,
+        latency: latency_millis(player),
+        display_name: None,
+    }
+}
+
+/// The latency measured by Keep Alive, in milliseconds.
+fn latency_millis(player: &Player) -> i32 {
+    i32::try_from(player.connection.latency.millis()).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use pandoranet::{actions::Movement, structs::login_struct::offline_uuid, Outgoing};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::game::test_util::{batches, init, online_player, PROTOCOL};
+
+    const SPAWN: Location = Location {
+        x: 0.5,
+        y: 64.0,
+        z: 0.5,
+        yaw: 0.0,
+        pitch: 0.0,
+    };
+
+    /// Adds player `name` on connection `connection_id` and returns its queue.
+    fn join(
+        world: &mut World,
+        outbox: &mut Outbox,
+        name: &str,
+        connection_id: u32,
+    ) -> mpsc::Receiver<Outgoing> {
+        let (player, queue) = online_player(name, connection_id, PROTOCOL);
+        world.apply(
+            PlayerAction::Joined {
+                uuid: offline_uuid(name),
+                player,
+            },
+            outbox,
+        );
+        queue
+    }
+
+    /// Flushes `outbox` and returns the ids of the packets sent to `queue`.
+    fn sent(outbox: &mut Outbox, queue: &mut mpsc::Receiver<Outgoing>) -> Vec<i32> {
+        outbox.flush();
+        batches(queue)
+            .into_iter()
+            .flatten()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn moved(name: &str, connection_id: u32, x: f64) -> PlayerAction {
+        PlayerAction::Moved {
+            uuid: offline_uuid(name),
+            connection_id,
+            movement: Movement {
+                position: Some((x, 64.0, 0.5)),
+                rotation: None,
+                on_ground: true,
+            },
+        }
+    }
+
+    #[test]
+    fn teleports_joining_players_to_the_spawn() {
+        init();
+        let mut world = World::new(SPAWN);
+        let mut outbox = Outbox::new();
+        let mut queue = join(&mut world, &mut outbox, "Tester", 1);
+
+        assert_eq!(sent(&mut outbox, &mut queue), [0x4e, 0x34, 0x32]);
+        let player = &world.players[&offline_uuid("Tester")];
+        assert_eq!(player.location, SPAWN);
+        assert!(player.is_teleporting());
+    }
+
+    #[test]
+    fn accepts_movement_once_the_spawn_teleport_is_confirmed() {
+        init();
+        let mut world = World::new(SPAWN);
+        let mut outbox = Outbox::new();
+        let mut queue = join(&mut world, &mut outbox, "Tester", 1);
+        sent(&mut outbox, &mut queue);
+        let uuid = offline_uuid("Tester");
+
+        world.apply(moved("Tester", 1, 1.5), &mut outbox);
+        assert_eq!(world.players[&uuid].location, SPAWN);
+
+        // A wrong id and a confirmation from another connection change nothing.
+        for (connection_id, teleport_id) in [(1, 2), (9, 1)] {
+            world.apply(
+                PlayerAction::TeleportConfirmed {
+                    uuid,
+                    connection_id,
+                    teleport_id,
+                },
+                &mut outbox,
+            );
+            assert!(world.players[&uuid].is_teleporting());
+        }
+
+        world.apply(
+            PlayerAction::TeleportConfirmed {
+                uuid,
+                connection_id: 1,
+                teleport_id: 1,
+            },
+            &mut outbox,
+        );
+        world.apply(moved("Tester", 1, 1.5), &mut outbox);
+        assert_eq!(world.players[&uuid].location, Location { x: 1.5, ..SPAWN });
+
+        // Moves from an old connection of the player are dropped.
+        world.apply(moved("Tester", 9, 2.5), &mut outbox);
+        assert_eq!(world.players[&uuid].location.x, 1.5);
+        assert!(sent(&mut outbox, &mut queue).is_empty());
+    }
+
+    #[test]
+    fn removes_players_that_left_from_the_tab_list() {
+        init();
+        let mut world = World::new(SPAWN);
+        let mut outbox = Outbox::new();
+        let mut first = join(&mut world, &mut outbox, "First", 1);
+        let mut second = join(&mut world, &mut outbox, "Second", 2);
+        assert_eq!(sent(&mut outbox, &mut first), [0x4e, 0x34, 0x32, 0x32]);
+        assert_eq!(sent(&mut outbox, &mut second), [0x4e, 0x34, 0x32]);
+
+        // Leaving from a connection the player no longer uses does nothing.
+        world.apply(
+            PlayerAction::Left {
+                uuid: offline_uuid("Second"),
+                connection_id: 1,
+            },
+            &mut outbox,
+        );
+        assert_eq!(world.players.len(), 2);
+
+        world.apply(
+            PlayerAction::Left {
+                uuid: offline_uuid("Second"),
+                connection_id: 2,
+            },
+            &mut outbox,
+        );
+        assert!(!world.players.contains_key(&offline_uuid("Second")));
+        assert_eq!(sent(&mut outbox, &mut first), [0x32]);
+        assert!(sent(&mut outbox, &mut second).is_empty());
+    }
+}
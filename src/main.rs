@@ -1,12 +1,13 @@
-#![feature(box_into_inner)]
-
-use log::{error, info};
+use game::{game_loop::GameLoop, player::Location, world::World};
+use log::{error, info, warn};
 use networking::server::start_server;
+use pandora_utils::config::Config;
+use pandoranet::actions::PLAYER_ACTIONS;
+use tokio_util::sync::CancellationToken;
 
 use std::time::Instant;
 
-pub mod config;
-pub mod constants;
+pub mod game;
 pub mod logger;
 pub mod networking;
 
@@ -17,11 +18,69 @@ async fn main() {
     info!("Starting Pandora");
 
     let start_time = Instant::now();
-    let config = config::Config::load_config();
+    let config = Config::get();
     let duration = start_time.elapsed();
     info!("Config loaded in {:?}", duration);
 
+    for name in &config.versions {
+        if !pandoranet::registry::VERSIONS
+            .iter()
+            .any(|version| version.name == name)
+        {
+            warn!("Ignoring unsupported version {} in config", name);
+        }
+    }
+    info!(
+        "Accepting Minecraft {}",
+        pandoranet::registry::enabled_version_names()
+    );
+
+    if config.online_mode {
+        pandoranet::auth::server_key();
+    }
+
+    for version in pandoranet::registry::enabled_versions() {
+        pandoranet::structs::play_struct::dimension_codec(version.protocol);
+    }
+
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup());
+
+    let actions = PLAYER_ACTIONS
+        .take_receiver()
+        .expect("player actions are only received by the game loop");
+    let stop_game_loop = CancellationToken::new();
+    let game_loop = tokio::spawn(
+        GameLoop::new(World::new(Location::from(config.spawn)), actions)
+            .run(stop_game_loop.clone()),
+    );
+
     if let Err(e) = start_server(&config).await {
         error!("Failed to start server: {}", e);
     }
+
+    stop_game_loop.cancel();
+    if let Err(e) = game_loop.await {
+        error!("Game loop failed: {}", e);
+    }
+}
+
+/// Reloads `config.json` (and with it the cached favicon) whenever the process receives SIGHUP.
+#[cfg(unix)]
+async fn reload_on_sighup() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        if let Err(e) = Config::reload() {
+            error!("Failed to reload config: {}", e);
+        }
+    }
 }
@@ -1,16 +1,118 @@
-use log::info;
-use tokio::net::TcpListener;
+use std::{sync::Arc, time::Duration};
 
-use crate::config::Config;
+use log::{error, info, warn};
+use tokio::{net::TcpListener, sync::Semaphore, time::timeout};
+use tokio_util::task::TaskTracker;
 
+use pandora_utils::config::Config;
+
+/// Delay before accepting again after `accept` failed, doubled on every failure
+/// in a row up to `MAX_ACCEPT_BACKOFF`. Usually the process ran out of file
+/// descriptors and retrying right away would spin.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+/// How long shutdown waits for connections to write their last packets.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts clients until Ctrl-C or SIGTERM, serving each one on its own task,
+/// then disconnects everyone and waits for their connections to close.
 pub async fn start_server(config: &Config) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr = format!("{}:{}", config.host, config.port);
     let listener = TcpListener::bind(&addr).await?;
     info!("Server listening on {}", addr);
 
+    let connection_slots = Arc::new(Semaphore::new(config.max_connections));
+    let connections = TaskTracker::new();
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
     loop {
-        let (socket, _) = listener.accept().await.unwrap();
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+
+        let (socket, addr) = match accepted {
+            Ok(accepted) => {
+                backoff = MIN_ACCEPT_BACKOFF;
+                accepted
+            }
+            Err(e) => {
+                error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+
+        let Ok(slot) = connection_slots.clone().try_acquire_owned() else {
+            warn!(
+                "Refusing connection from {}: {} connections are open",
+                addr, config.max_connections
+            );
+            continue;
+        };
+
+        if let Err(e) = socket.set_nodelay(config.tcp_nodelay) {
+            warn!("Failed to set TCP_NODELAY for {}: {}", addr, e);
+        }
+
+        connections.spawn(async move {
+            if let Err(e) = pandoranet::handle_connection(socket).await {
+                error!("Connection from {} failed: {}", addr, e);
+            }
+            drop(slot);
+        });
+    }
+
+    info!("Shutting down, closing {} connections", connections.len());
+    drop(listener);
+
+    pandoranet::CONNECTION_MANAGER.close_all("Server closed");
+
+    connections.close();
+    if timeout(SHUTDOWN_TIMEOUT, connections.wait()).await.is_err() {
+        warn!(
+            "{} connections did not close within {:?}",
+            connections.len(),
+            SHUTDOWN_TIMEOUT
+        );
+    }
+
+    Ok(())
+}
+
+/// Completes on Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-        pandoranet::handle_connection(socket).await.unwrap();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }